@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Seek},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use zip::ZipArchive;
+
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+enum AssetSource {
+    Archive(Mutex<ZipArchive<Box<dyn ReadSeek>>>),
+    Dir(PathBuf),
+}
+
+/// Files of a beatmap set, shared by all of its difficulties.
+///
+/// Entries are only read or decompressed when first requested, and assets fetched
+/// with `get` are cached so every difficulty shares a single copy of e.g. the audio.
+pub struct Assets {
+    source: AssetSource,
+    names: Vec<String>,
+    cache: Mutex<HashMap<String, Arc<[u8]>>>,
+}
+
+impl Assets {
+    pub fn from_archive<R: Read + Seek + Send + 'static>(
+        reader: R,
+    ) -> zip::result::ZipResult<Self> {
+        let archive = ZipArchive::new(Box::new(reader) as Box<dyn ReadSeek>)?;
+        let names = archive.file_names().map(String::from).collect();
+
+        Ok(Self {
+            source: AssetSource::Archive(Mutex::new(archive)),
+            names,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn from_dir(path: &Path) -> io::Result<Self> {
+        let mut names = Vec::new();
+        list_dir(path, "", &mut names)?;
+
+        Ok(Self {
+            source: AssetSource::Dir(path.into()),
+            names,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Only the files directly inside `path`, for a loose file whose folder may hold far more
+    /// than its beatmap. Anything in subfolders can still be read by name.
+    pub fn from_files_in(path: &Path) -> io::Result<Self> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(path)?.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_file()) {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        Ok(Self {
+            source: AssetSource::Dir(path.into()),
+            names,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Paths of every file, `/` separated like zip entries.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Reads a file without caching it.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        match &self.source {
+            AssetSource::Archive(archive) => {
+                let mut archive = archive.lock().unwrap();
+                let mut file = archive.by_name(name)?;
+                let mut bytes = Vec::with_capacity(file.size() as usize);
+                file.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            AssetSource::Dir(dir) => std::fs::read(dir.join(name)),
+        }
+    }
+
+    /// Returns a shared copy of a file, reading it on first use.
+    pub fn get(&self, name: &str) -> io::Result<Arc<[u8]>> {
+        if let Some(bytes) = self.cache.lock().unwrap().get(name) {
+            return Ok(bytes.clone());
+        }
+
+        let bytes: Arc<[u8]> = self.read(name)?.into();
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(name.into(), bytes.clone());
+        Ok(bytes)
+    }
+}
+
+fn list_dir(dir: &Path, prefix: &str, names: &mut Vec<String>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+
+        if entry.file_type()?.is_dir() {
+            list_dir(&entry.path(), &format!("{}/", name), names)?;
+        } else {
+            names.push(name);
+        }
+    }
+
+    Ok(())
+}
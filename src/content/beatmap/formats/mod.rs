@@ -1 +1,274 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::{Beatmap, assets::Assets};
+
 pub mod osu;
+pub mod zoku;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Osu(osu::Error),
+    Zoku(zoku::Error),
+    Io(std::io::Error),
+    UnknownFormat(PathBuf),
+    Unsupported(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Osu(e) => e.fmt(formatter),
+            Error::Zoku(e) => e.fmt(formatter),
+            Error::Io(e) => write!(formatter, "I/O error: {}", e),
+            Error::UnknownFormat(path) => {
+                write!(
+                    formatter,
+                    "{} is not a known beatmap format",
+                    path.display()
+                )
+            }
+            Error::Unsupported(reason) => formatter.write_str(reason),
+        }
+    }
+}
+
+impl From<osu::Error> for Error {
+    fn from(e: osu::Error) -> Self {
+        Error::Osu(e)
+    }
+}
+
+impl From<zoku::Error> for Error {
+    fn from(e: zoku::Error) -> Self {
+        Error::Zoku(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A way of storing beatmaps on disk.
+pub trait BeatmapFormat {
+    fn name(&self) -> &'static str;
+
+    /// Whether `path` holds this format, judging by its extension or its first bytes.
+    /// `magic` is empty for directories and files that don't exist yet.
+    fn detect(&self, path: &Path, magic: &[u8]) -> bool;
+
+    /// In lenient mode recoverable problems end up in `Beatmap::warnings`.
+    fn load(&self, path: &Path, lenient: bool) -> Result<Beatmap>;
+
+    /// Loads a beatmap that is already in memory, for formats that can hold their assets.
+    fn load_bytes(&self, _bytes: Vec<u8>, _lenient: bool) -> Result<Beatmap> {
+        Err(Error::Unsupported(format!(
+            "Loading a {} from memory is not supported",
+            self.name()
+        )))
+    }
+
+    fn save(&self, _beatmap: &Beatmap, _path: &Path) -> Result<()> {
+        Err(Error::Unsupported(format!(
+            "Saving {} is not supported",
+            self.name()
+        )))
+    }
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// The folder a file is in, `.` for a bare file name.
+fn folder_of(path: &Path) -> &Path {
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// A single difficulty, used when saving to a format that holds only one.
+fn single_difficulty<'a>(beatmap: &'a Beatmap, format: &str) -> Result<&'a super::Difficulty> {
+    match beatmap.difficulties.as_slice() {
+        [diff] => Ok(diff),
+        _ => Err(Error::Unsupported(format!(
+            "A {} holds exactly one difficulty, not {}",
+            format,
+            beatmap.difficulties.len()
+        ))),
+    }
+}
+
+pub struct OszFormat;
+
+impl BeatmapFormat for OszFormat {
+    fn name(&self) -> &'static str {
+        ".osz archive"
+    }
+
+    fn detect(&self, path: &Path, magic: &[u8]) -> bool {
+        has_extension(path, "osz") || magic.starts_with(b"PK\x03\x04")
+    }
+
+    fn load(&self, path: &Path, lenient: bool) -> Result<Beatmap> {
+        Ok(osu::OsuParser::from_osz(path, lenient)?)
+    }
+
+    fn load_bytes(&self, bytes: Vec<u8>, lenient: bool) -> Result<Beatmap> {
+        Ok(osu::OsuParser::from_bytes(bytes, lenient)?)
+    }
+
+    fn save(&self, beatmap: &Beatmap, path: &Path) -> Result<()> {
+        Ok(osu::OsuWriter::to_osz(beatmap, path)?)
+    }
+}
+
+pub struct OsuFormat;
+
+impl BeatmapFormat for OsuFormat {
+    fn name(&self) -> &'static str {
+        ".osu file"
+    }
+
+    fn detect(&self, path: &Path, magic: &[u8]) -> bool {
+        let magic = magic.strip_prefix("\u{feff}".as_bytes()).unwrap_or(magic);
+        has_extension(path, "osu") || magic.starts_with(b"osu file format")
+    }
+
+    fn load(&self, path: &Path, lenient: bool) -> Result<Beatmap> {
+        Ok(osu::OsuParser::from_osu_file(path, lenient)?)
+    }
+
+    fn save(&self, beatmap: &Beatmap, path: &Path) -> Result<()> {
+        let diff = single_difficulty(beatmap, self.name())?;
+        Ok(std::fs::write(path, osu::OsuWriter::to_osu(diff))?)
+    }
+}
+
+/// An extracted beatmap set.
+pub struct FolderFormat;
+
+impl BeatmapFormat for FolderFormat {
+    fn name(&self) -> &'static str {
+        "beatmap folder"
+    }
+
+    fn detect(&self, path: &Path, _magic: &[u8]) -> bool {
+        path.is_dir()
+    }
+
+    fn load(&self, path: &Path, lenient: bool) -> Result<Beatmap> {
+        Ok(osu::OsuParser::from_dir(path, lenient)?)
+    }
+}
+
+pub struct ZokuFormat;
+
+impl BeatmapFormat for ZokuFormat {
+    fn name(&self) -> &'static str {
+        ".zoku file"
+    }
+
+    fn detect(&self, path: &Path, magic: &[u8]) -> bool {
+        has_extension(path, "zoku") || magic.starts_with(b"format = ")
+    }
+
+    /// Assets are taken from the folder the file is in.
+    fn load(&self, path: &Path, _lenient: bool) -> Result<Beatmap> {
+        let map = zoku::ZokuParser::from_file(path)?;
+        let assets = Assets::from_files_in(folder_of(path))?;
+
+        let mut diff = super::Difficulty::from(&map);
+        diff.assets = Some(Arc::new(assets));
+
+        Ok(Beatmap {
+            difficulties: vec![diff],
+            warnings: Vec::new(),
+        })
+    }
+
+    fn save(&self, beatmap: &Beatmap, path: &Path) -> Result<()> {
+        let diff = single_difficulty(beatmap, self.name())?;
+        zoku::ZokuWriter::to_file(&zoku::ZokuMap::from(diff), path)?;
+        Ok(())
+    }
+}
+
+/// Picks the format for a path, so callers don't need to know which ones exist.
+pub struct Registry {
+    formats: Vec<Box<dyn BeatmapFormat>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(FolderFormat);
+        registry.register(OszFormat);
+        registry.register(OsuFormat);
+        registry.register(ZokuFormat);
+        registry
+    }
+}
+
+impl Registry {
+    /// A registry without any formats, see `Registry::default` for the built-in ones.
+    pub fn new() -> Self {
+        Self {
+            formats: Vec::new(),
+        }
+    }
+
+    /// Formats are tried in the order they were registered.
+    pub fn register(&mut self, format: impl BeatmapFormat + 'static) {
+        self.formats.push(Box::new(format));
+    }
+
+    pub fn detect(&self, path: &Path) -> Option<&dyn BeatmapFormat> {
+        let mut magic = Vec::with_capacity(16);
+        if path.is_file()
+            && let Ok(file) = File::open(path)
+        {
+            let _ = file.take(16).read_to_end(&mut magic);
+        }
+
+        self.formats
+            .iter()
+            .map(|f| f.as_ref())
+            .find(|f| f.detect(path, &magic))
+    }
+
+    pub fn load(&self, path: &Path, lenient: bool) -> Result<Beatmap> {
+        self.detect(path)
+            .ok_or_else(|| Error::UnknownFormat(path.into()))?
+            .load(path, lenient)
+    }
+
+    /// The format is chosen by the first bytes alone.
+    pub fn load_bytes(&self, bytes: Vec<u8>, lenient: bool) -> Result<Beatmap> {
+        let magic = &bytes[..bytes.len().min(16)];
+        self.formats
+            .iter()
+            .find(|f| f.detect(Path::new(""), magic))
+            .ok_or_else(|| Error::UnknownFormat("<memory>".into()))?
+            .load_bytes(bytes, lenient)
+    }
+
+    /// The format is chosen by the extension of `path`.
+    pub fn save(&self, beatmap: &Beatmap, path: &Path) -> Result<()> {
+        self.formats
+            .iter()
+            .find(|f| f.detect(path, &[]))
+            .ok_or_else(|| Error::UnknownFormat(path.into()))?
+            .save(beatmap, path)
+    }
+}
@@ -1,7 +1,14 @@
-use crate::content::beatmap::*;
+use crate::content::beatmap::{assets::Assets, *};
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::{collections::HashMap, fmt::Display, io::Read};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Cursor, Seek, Write},
+    path::Path,
+    sync::Arc,
+};
+use zip::{ZipWriter, write::SimpleFileOptions};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -10,6 +17,23 @@ pub enum Error {
     Parse,
     Syntax(String),
     Message(String),
+    Io(std::io::Error),
+    Archive(zip::result::ZipError),
+    Invalid {
+        value: String,
+        expected: &'static str,
+    },
+    Located(Location, Box<Error>),
+}
+
+/// Where in a `.osu` file an error happened.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub section: Option<String>,
+    pub text: String,
 }
 
 impl Display for Error {
@@ -17,18 +41,60 @@ impl Display for Error {
         match *self {
             Error::Message(ref msg) => formatter.write_str(msg),
             Error::Syntax(ref reason) => write!(formatter, "Syntax error: {}", reason),
-            Error::Parse => formatter.write_str("Parsing error"),
+            Error::Parse => formatter.write_str("Parsing error: missing value"),
+            Error::Io(ref e) => write!(formatter, "I/O error: {}", e),
+            Error::Archive(ref e) => write!(formatter, "Invalid .osz archive: {}", e),
+            Error::Invalid {
+                ref value,
+                expected,
+            } => write!(formatter, "Expected {}, found '{}'", expected, value),
+            Error::Located(ref location, ref error) => {
+                write!(
+                    formatter,
+                    "{}:{}:{}",
+                    location.file.as_deref().unwrap_or("<input>"),
+                    location.line,
+                    location.column
+                )?;
+                if let Some(section) = &location.section {
+                    write!(formatter, " [{}]", section)?;
+                }
+                write!(formatter, ": {}\n    {}", error, location.text)
+            }
         }
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(e: zip::result::ZipError) -> Self {
+        Error::Archive(e)
+    }
+}
+
 pub struct OsuParser {
-    files: HashMap<String, Vec<u8>>,
+    assets: Arc<Assets>,
+    lenient: bool,
+    warnings: Vec<Error>,
 }
 
+type NonEmptyLines<'a> =
+    std::iter::Filter<std::iter::Enumerate<std::str::Lines<'a>>, fn(&(usize, &str)) -> bool>;
+
 pub struct ParseState<'a> {
-    lines: std::iter::Filter<std::str::Lines<'a>, fn(&&str) -> bool>,
+    lines: NonEmptyLines<'a>,
     current_line: Option<&'a str>,
+    line_number: usize,
+    section: Option<&'a str>,
+    file: Option<String>,
+    lenient: bool,
+    seen_keys: Vec<&'a str>,
+    pub warnings: Vec<Error>,
 }
 
 impl<'a> ParseState<'a> {
@@ -38,9 +104,79 @@ impl<'a> ParseState<'a> {
         }
 
         ParseState {
-            lines: input.lines().filter(|l| !EMPTY_LINE.is_match(l)),
+            lines: input
+                .trim_start_matches('\u{feff}')
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| !EMPTY_LINE.is_match(l)),
             current_line: None,
+            line_number: 0,
+            section: None,
+            file: None,
+            lenient: false,
+            seen_keys: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Names the file in error locations.
+    pub fn file(mut self, name: &str) -> Self {
+        self.file = Some(name.into());
+        self
+    }
+
+    /// Skips malformed lines, collecting them in `warnings` instead of failing, like the
+    /// official client does. Unknown keys are kept in their section's `unknown` either way.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Attaches the current line to an error.
+    pub fn locate(&self, error: Error) -> Error {
+        if let Error::Located(..) = error {
+            return error;
         }
+
+        let text = self.current_line.unwrap_or_default();
+        let column = match &error {
+            Error::Invalid { value, .. } if !value.is_empty() => {
+                text.find(value.as_str()).map_or(1, |c| c + 1)
+            }
+            _ => 1,
+        };
+
+        Error::Located(
+            Location {
+                file: self.file.clone(),
+                line: self.line_number,
+                column,
+                section: self.section.map(String::from),
+                text: text.into(),
+            },
+            Box::new(error),
+        )
+    }
+
+    /// Fails in strict mode, records a warning and carries on in lenient mode.
+    pub fn recover(&mut self, error: Error) -> Result<()> {
+        let error = self.locate(error);
+        if self.lenient {
+            self.warnings.push(error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    /// Whether a key/value section contained `key`, for keys whose absence means something.
+    pub fn seen(&self, key: &str) -> bool {
+        self.seen_keys.contains(&key)
+    }
+
+    /// True once the current line is a section header or the input is exhausted.
+    pub fn at_section_end(&self) -> bool {
+        self.current_line.is_none_or(is_header)
     }
     pub fn get_current_line(&mut self) -> Option<&'a str> {
         if let Some(line) = self.current_line {
@@ -51,19 +187,39 @@ impl<'a> ParseState<'a> {
     }
 
     pub fn read_next_line(&mut self) -> Option<&'a str> {
-        let next_line = self.lines.next();
+        let next_line = self.lines.next().map(|(i, l)| {
+            self.line_number = i + 1;
+            l
+        });
         self.current_line = next_line;
 
         next_line
     }
 }
 
+fn is_header(line: &str) -> bool {
+    lazy_static! {
+        static ref HEADER_RE: Regex = Regex::new(r"^\[([^\[\]]*)\]\s*$").unwrap();
+    }
+
+    HEADER_RE.is_match(line)
+}
+
 macro_rules! read_val {
     ($iter:ident, $func:expr) => {
         $iter.next().ok_or(Error::Parse).and_then($func)
     };
 }
 
+macro_rules! read_opt {
+    ($iter:ident, $func:expr, $default:expr) => {
+        match $iter.next().filter(|s| !s.is_empty()) {
+            Some(s) => $func(s)?,
+            None => $default,
+        }
+    };
+}
+
 macro_rules! read_list {
     ($sep:expr, $iter:ident, $func:expr) => {
         $iter
@@ -73,50 +229,38 @@ macro_rules! read_list {
     };
 }
 
-macro_rules! parse_into_struct {
-	($sep:expr, $dest:ident, $line:expr; {$($field:ident: $f:expr),*}) => {
-		{
-			let mut iter = $line.split($sep).map(|s| s.trim());
-			$dest {
-				$($field: {
-					$f(iter.next()
-						.ok_or_else(|| Error::Syntax(format!(
-							"Unable to read field {} into struct {}",
-							stringify!($field),
-							stringify!($dest)))
-						)?)?
-				}),*
-			}
-		}
-	};
-}
-
 macro_rules! value_parser {
     ($v:expr, $fn:expr) => {
         $fn($v)
     };
     ($v:expr, $fn:expr, $sep:expr) => {
         $v.split($sep)
-            .map(|s| $fn(s.trim()))
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map($fn)
             .collect::<std::result::Result<Vec<_>, _>>()
     };
 }
 
-pub fn parse_kv_pair<'a>(state: &'a mut ParseState) -> Option<(&'a str, &'a str)> {
+pub fn parse_kv_pair<'a>(state: &mut ParseState<'a>) -> Option<(&'a str, &'a str)> {
     lazy_static! {
         static ref RE: Regex = Regex::new(r"^(\S+)\s*:(\s*(\S.*)$)?").unwrap();
     }
 
-    state
-        .read_next_line()
-        .and_then(|l| RE.captures(l))
-        .and_then(|c| {
-            c.get(1).and_then(|k| {
-                c.get(3).map_or(Some((k.as_str(), "none")), |v| {
-                    Some((k.as_str(), v.as_str()))
-                })
-            })
+    // The official client ignores comments anywhere in a section
+    let line = loop {
+        let line = state.read_next_line()?;
+        if !line.trim_start().starts_with("//") {
+            break line;
+        }
+    };
+
+    RE.captures(line).and_then(|c| {
+        c.get(1).and_then(|k| {
+            c.get(3)
+                .map_or(Some((k.as_str(), "")), |v| Some((k.as_str(), v.as_str())))
         })
+    })
 }
 
 macro_rules! parse_kv_section {
@@ -127,9 +271,17 @@ macro_rules! parse_kv_section {
             loop {
                 match parse_kv_pair($state) {
                     $(
-                    Some(($str, v)) => section.$field = value_parser!(v, $($f),*)?,
+                    Some(($str, v)) => {
+                        $state.seen_keys.push($str);
+                        match value_parser!(v, $($f),*) {
+                            Ok(value) => section.$field = value,
+                            Err(e) => $state.recover(e)?,
+                        }
+                    }
                     )*
-                    _ => break,
+                    Some((k, v)) => section.unknown.push((k.into(), v.into())),
+                    None if $state.at_section_end() => break,
+                    None => $state.recover(Error::Syntax(String::from("Expected key: value pair")))?,
                 }
             }
 
@@ -145,8 +297,16 @@ macro_rules! make_syntax_err {
 }
 
 pub fn parse_num<T: std::str::FromStr>(n: &str) -> Result<T> {
-    n.parse()
-        .map_err(|_| Error::Syntax(String::from("Unable to parse number")))
+    n.parse().map_err(|_| Error::Invalid {
+        value: n.into(),
+        expected: "number",
+    })
+}
+
+/// Older maps sometimes store times and positions as decimals, which the official
+/// client truncates.
+pub fn parse_truncated(s: &str) -> Result<i32> {
+    parse_num::<f64>(s).map(|n| n as i32)
 }
 
 pub fn parse_string(s: &str) -> Result<String> {
@@ -156,7 +316,10 @@ pub fn parse_string(s: &str) -> Result<String> {
 pub fn parse_bool(s: &str) -> Result<bool> {
     s.parse::<i32>()
         .map(|n| n != 0)
-        .map_err(|_| Error::Syntax(String::from("Could not parse bool")))
+        .map_err(|_| Error::Invalid {
+            value: s.into(),
+            expected: "0 or 1",
+        })
 }
 
 pub fn parse_mode(s: &str) -> Result<GameMode> {
@@ -165,10 +328,77 @@ pub fn parse_mode(s: &str) -> Result<GameMode> {
         "1" => Ok(GameMode::Taiko),
         "2" => Ok(GameMode::CTB),
         "3" => Ok(GameMode::Mania),
-        _ => Err(Error::Syntax(String::from("Unable to parse gamemode"))),
+        _ => Err(Error::Invalid {
+            value: s.into(),
+            expected: "game mode",
+        }),
+    }
+}
+
+pub fn parse_countdown(s: &str) -> Result<Countdown> {
+    match s {
+        "0" => Ok(Countdown::None),
+        "1" => Ok(Countdown::Normal),
+        "2" => Ok(Countdown::Half),
+        "3" => Ok(Countdown::Double),
+        _ => Err(Error::Invalid {
+            value: s.into(),
+            expected: "countdown speed",
+        }),
+    }
+}
+
+pub fn parse_overlay_position(s: &str) -> Result<OverlayPosition> {
+    match s {
+        "NoChange" => Ok(OverlayPosition::NoChange),
+        "Below" => Ok(OverlayPosition::Below),
+        "Above" => Ok(OverlayPosition::Above),
+        _ => Err(Error::Invalid {
+            value: s.into(),
+            expected: "overlay position",
+        }),
+    }
+}
+
+/// Bits beyond the four known sounds are ignored, as in the official client.
+pub fn parse_hitsound(s: &str) -> Result<HitSound> {
+    parse_num::<i32>(s).map(|n| HitSound::from_bits_truncate(n as u8))
+}
+
+pub fn parse_sample_set(s: &str) -> Result<SampleSet> {
+    match s {
+        "0" => Ok(SampleSet::Auto),
+        "1" => Ok(SampleSet::Normal),
+        "2" => Ok(SampleSet::Soft),
+        "3" => Ok(SampleSet::Drum),
+        _ => Err(Error::Invalid {
+            value: s.into(),
+            expected: "sample set",
+        }),
+    }
+}
+
+/// `[General]` names the default sample set instead of numbering it.
+pub fn parse_sample_set_name(s: &str) -> Result<SampleSet> {
+    match s {
+        "Normal" => Ok(SampleSet::Normal),
+        "Soft" => Ok(SampleSet::Soft),
+        "Drum" => Ok(SampleSet::Drum),
+        _ => Err(Error::Invalid {
+            value: s.into(),
+            expected: "Normal, Soft or Drum",
+        }),
     }
 }
 
+fn parse_edge_sets(s: &str) -> Result<(SampleSet, SampleSet)> {
+    let mut iter = s.split(":");
+    Ok((
+        read_val!(iter, parse_sample_set)?,
+        read_val!(iter, parse_sample_set)?,
+    ))
+}
+
 pub fn parse_colour(s: &str) -> Result<Colour> {
     let mut iter = s.split(",");
     Ok(Colour(
@@ -178,14 +408,16 @@ pub fn parse_colour(s: &str) -> Result<Colour> {
     ))
 }
 
+/// Older versions only write the leading fields, the rest keep their defaults.
 pub fn parse_extras(s: &str) -> Result<HitObjectExtras> {
-    Ok(parse_into_struct!(":", HitObjectExtras, s; {
-        sample_set: parse_num,
-        addition_set: parse_num,
-        custom_index: parse_num,
-        sample_volume: parse_num,
-        filename: parse_string
-    }))
+    let mut iter = s.split(":").map(|s| s.trim());
+    Ok(HitObjectExtras {
+        sample_set: read_opt!(iter, parse_sample_set, SampleSet::Auto),
+        addition_set: read_opt!(iter, parse_sample_set, SampleSet::Auto),
+        custom_index: read_opt!(iter, parse_num, 0),
+        sample_volume: read_opt!(iter, parse_num, 0),
+        filename: read_opt!(iter, parse_string, String::new()),
+    })
 }
 
 pub fn parse_slider_type(s: &str) -> Result<SliderType> {
@@ -194,7 +426,10 @@ pub fn parse_slider_type(s: &str) -> Result<SliderType> {
         "B" => Ok(SliderType::Bezier),
         "P" => Ok(SliderType::Perfect),
         "C" => Ok(SliderType::Catmull),
-        _ => Err(Error::Syntax(String::from("Invalid slider type"))),
+        _ => Err(Error::Invalid {
+            value: s.into(),
+            expected: "slider type",
+        }),
     }
 }
 
@@ -213,18 +448,57 @@ fn parse_curve_points(s: &str) -> Result<(SliderType, Vec<(i32, i32)>)> {
     Ok((slider_type, points))
 }
 
+/// Backgrounds, videos and breaks are parsed, anything else is kept as written.
+pub fn parse_event(s: &str) -> Result<Event> {
+    // Storyboard commands are indented with spaces or underscores
+    if s.starts_with("//") || s.starts_with([' ', '_']) {
+        return Ok(Event::Other(s.into()));
+    }
+
+    let mut iter = s.split(",").map(|s| s.trim());
+
+    match iter.next() {
+        Some("0" | "Background") => {
+            let _start_time: i32 = read_val!(iter, parse_truncated)?;
+            Ok(Event::Background {
+                filename: read_val!(iter, parse_filename)?,
+                x: read_opt!(iter, parse_num, 0),
+                y: read_opt!(iter, parse_num, 0),
+            })
+        }
+
+        Some("1" | "Video") => Ok(Event::Video {
+            start_time: read_val!(iter, parse_truncated)?,
+            filename: read_val!(iter, parse_filename)?,
+            x: read_opt!(iter, parse_num, 0),
+            y: read_opt!(iter, parse_num, 0),
+        }),
+
+        Some("2" | "Break") => Ok(Event::Break {
+            start_time: read_val!(iter, parse_truncated)?,
+            end_time: read_val!(iter, parse_truncated)?,
+        }),
+
+        _ => Ok(Event::Other(s.into())),
+    }
+}
+
+pub fn parse_filename(s: &str) -> Result<String> {
+    Ok(String::from(s.trim_matches('"')))
+}
+
 pub fn parse_hit_object(s: &str) -> Result<HitObject> {
     let mut iter = s.split(",");
 
-    let x: i32 = read_val!(iter, parse_num)?;
-    let y: i32 = read_val!(iter, parse_num)?;
-    let time: i32 = read_val!(iter, parse_num)?;
+    let x: i32 = read_val!(iter, parse_truncated)?;
+    let y: i32 = read_val!(iter, parse_truncated)?;
+    let time: i32 = read_val!(iter, parse_truncated)?;
     let obj_type: i32 = read_val!(iter, parse_num)?;
 
     let new_combo = obj_type & 4 != 0;
     let color_skip = (obj_type >> 4) & 7;
 
-    let hitsound = read_val!(iter, parse_num)?;
+    let hitsound = read_val!(iter, parse_hitsound)?;
 
     match obj_type & 139 {
         1 => Ok(HitObject::HitCircle(HitCircle {
@@ -253,9 +527,9 @@ pub fn parse_hit_object(s: &str) -> Result<HitObject> {
                 repeat: read_val!(iter, parse_num)?,
                 pixel_length: read_val!(iter, parse_num)?,
 
-                edge_hitsounds: read_list!("|", iter, parse_num).unwrap_or(Vec::new()),
+                edge_hitsounds: read_list!("|", iter, parse_hitsound).unwrap_or(Vec::new()),
 
-                edge_additions: read_list!("|", iter, parse_coord).unwrap_or(Vec::new()),
+                edge_additions: read_list!("|", iter, parse_edge_sets).unwrap_or(Vec::new()),
 
                 extras: read_val!(iter, parse_extras).unwrap_or(Default::default()),
             }))
@@ -269,68 +543,120 @@ pub fn parse_hit_object(s: &str) -> Result<HitObject> {
             color_skip,
             hitsound,
 
-            end_time: read_val!(iter, parse_num)?,
+            end_time: read_val!(iter, parse_truncated)?,
 
             extras: read_val!(iter, parse_extras).unwrap_or(Default::default()),
         })),
 
-        128 => Ok(HitObject::HoldNote(HoldNote {
-            x,
-            y,
-            time,
-            new_combo,
-            color_skip,
-            hitsound,
-
-            end_time: read_val!(iter, parse_num)?,
+        128 => {
+            // The end time leads the extras, e.g. `1500:0:0:0:0:`
+            let field = iter.next().ok_or(Error::Parse)?;
+            let (end_time, extras) = match field.split_once(":") {
+                Some((end_time, extras)) => (parse_truncated(end_time)?, parse_extras(extras)?),
+                None => (parse_truncated(field)?, Default::default()),
+            };
 
-            extras: read_val!(iter, parse_extras).unwrap_or(Default::default()),
-        })),
+            Ok(HitObject::HoldNote(HoldNote {
+                x,
+                y,
+                time,
+                new_combo,
+                color_skip,
+                hitsound,
+                end_time,
+                extras,
+            }))
+        }
 
-        _ => Err(Error::Syntax(String::from("Invalid hit object type"))),
+        _ => Err(Error::Syntax(format!(
+            "Invalid hit object type {}",
+            obj_type
+        ))),
     }
 }
 
 impl OsuParser {
-    pub fn new() -> Self {
+    pub fn new(assets: Assets, lenient: bool) -> Self {
         Self {
-            files: HashMap::new(),
+            assets: Arc::new(assets),
+            lenient,
+            warnings: Vec::new(),
         }
     }
 
-    pub fn from_osz(path: String) -> Beatmap {
-        let mut parser = Self::new();
+    /// In lenient mode broken lines and whole broken difficulties are skipped and
+    /// reported in `Beatmap::warnings`; otherwise the first error aborts loading.
+    pub fn from_osz(path: impl AsRef<Path>, lenient: bool) -> Result<Beatmap> {
+        let file = File::open(path)?;
+        Self::new(Assets::from_archive(file)?, lenient).load_all()
+    }
 
-        let file = std::fs::File::open(path.clone())
-            .expect(&format!("Failed to open beatmap file {}", path));
+    /// Loads an `.osz` archive that is already in memory.
+    pub fn from_bytes(bytes: Vec<u8>, lenient: bool) -> Result<Beatmap> {
+        Self::new(Assets::from_archive(Cursor::new(bytes))?, lenient).load_all()
+    }
 
-        let mut archive = zip::ZipArchive::new(file).expect("Failed to parse osz archive");
-        for i in 0..archive.len() {
-            let file = archive.by_index(i).unwrap();
-            let outpath = file.name();
-            parser.files.insert(
-                outpath.into(),
-                file.bytes()
-                    .collect::<std::result::Result<Vec<_>, std::io::Error>>()
-                    .unwrap(),
-            );
-        }
+    /// Loads every difficulty in an extracted beatmap folder.
+    pub fn from_dir(path: impl AsRef<Path>, lenient: bool) -> Result<Beatmap> {
+        Self::new(Assets::from_dir(path.as_ref())?, lenient).load_all()
+    }
 
+    /// Loads a single difficulty, taking its audio and other assets from the same folder.
+    pub fn from_osu_file(path: impl AsRef<Path>, lenient: bool) -> Result<Beatmap> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| Error::Message(format!("{} is not a file", path.display())))?;
+
+        let assets = Assets::from_files_in(super::folder_of(path))?;
+        Self::new(assets, lenient).load(vec![name.into()])
+    }
+
+    fn load_all(self) -> Result<Beatmap> {
+        let mut names: Vec<String> = self
+            .assets
+            .names()
+            .iter()
+            .filter(|f| f.ends_with(".osu"))
+            .cloned()
+            .collect();
+        names.sort();
+
+        self.load(names)
+    }
+
+    fn load(mut self, names: Vec<String>) -> Result<Beatmap> {
         let mut difficulties: Vec<Difficulty> = vec![];
 
-        for (file, _) in parser.files.clone() {
-            if file.ends_with(".osu") {
-                difficulties.push(parser.from_osu(file));
+        for name in names {
+            match self.parse_osu(name) {
+                Ok(diff) => difficulties.push(diff),
+                Err(e) if self.lenient => self.warnings.push(e),
+                Err(e) => return Err(e),
             }
         }
 
-        Beatmap { difficulties }
-    }
+        if difficulties.is_empty() {
+            return Err(Error::Message(String::from(
+                "No playable difficulties found",
+            )));
+        }
 
-    pub fn parse_difficulty(input: &str) -> Result<Difficulty> {
-        let mut state = ParseState::new(input);
+        Ok(Beatmap {
+            difficulties,
+            warnings: self.warnings,
+        })
+    }
 
-        let version = Self::parse_version_string(&mut state)?;
+    pub fn parse_difficulty(state: &mut ParseState) -> Result<Difficulty> {
+        let version = Self::parse_version_string(state).map_err(|e| state.locate(e))?;
+        if !(3..=14).contains(&version) {
+            state.recover(Error::Syntax(format!(
+                "Unsupported format version v{}",
+                version
+            )))?;
+        }
         state.read_next_line();
 
         let mut map = Difficulty {
@@ -339,7 +665,7 @@ impl OsuParser {
         };
 
         loop {
-            match Self::parse_section(&mut state)? {
+            match Self::parse_section(state)? {
                 Section::General(s) => map.general = s,
                 Section::Editor(s) => map.editor = s,
                 Section::Metadata(s) => map.metadata = s,
@@ -347,14 +673,78 @@ impl OsuParser {
                 Section::HitObjects(s) => map.hit_objects = s,
                 Section::Difficulty(s) => map.difficulty = s,
                 Section::Colours(s) => map.colours = s,
-                Section::Events => {}
+                Section::Events(s) => map.events = s,
+                Section::Skipped => {}
                 Section::None => break,
             }
         }
 
+        Self::apply_version_quirks(&mut map, state);
+        map.preprocess();
+
         Ok(map)
     }
 
+    /// Reproduces how the official client treats files written by older versions.
+    fn apply_version_quirks(map: &mut Difficulty, state: &ParseState) {
+        // ApproachRate was split from OverallDifficulty in v8
+        if !state.seen("ApproachRate") {
+            map.difficulty.approach_rate = map.difficulty.overall_difficulty;
+        }
+
+        // Sliders from before per-edge hitsounds play the slider's hitsound on every edge. Their
+        // edge sample sets are left empty, which already falls back to the slider's.
+        for object in &mut map.hit_objects {
+            if let HitObject::Slider(o) = object
+                && o.edge_hitsounds.is_empty()
+            {
+                o.edge_hitsounds = vec![o.hitsound; o.repeat.max(0) as usize + 1];
+            }
+        }
+
+        // Files before v5 were timed 24ms early
+        if map.version < 5 {
+            const OFFSET: i32 = 24;
+
+            if map.general.preview_time != -1 {
+                map.general.preview_time += OFFSET;
+            }
+
+            for point in &mut map.timing_points {
+                point.offset += OFFSET as f32;
+            }
+
+            for object in &mut map.hit_objects {
+                match object {
+                    HitObject::HitCircle(o) => o.time += OFFSET,
+                    HitObject::Slider(o) => o.time += OFFSET,
+                    HitObject::Spinner(o) => {
+                        o.time += OFFSET;
+                        o.end_time += OFFSET;
+                    }
+                    HitObject::HoldNote(o) => {
+                        o.time += OFFSET;
+                        o.end_time += OFFSET;
+                    }
+                }
+            }
+
+            for event in &mut map.events {
+                match event {
+                    Event::Video { start_time, .. } => *start_time += OFFSET,
+                    Event::Break {
+                        start_time,
+                        end_time,
+                    } => {
+                        *start_time += OFFSET;
+                        *end_time += OFFSET;
+                    }
+                    Event::Background { .. } | Event::Other(_) => {}
+                }
+            }
+        }
+    }
+
     fn parse_section(state: &mut ParseState) -> Result<Section> {
         if let Some(header_line) = state.get_current_line() {
             lazy_static! {
@@ -363,20 +753,28 @@ impl OsuParser {
 
             println!("Header: '{}'", header_line);
 
-            let section_title = HEADER_RE
+            let Some(section_title) = HEADER_RE
                 .captures(header_line)
                 .and_then(|c| c.get(1))
                 .map(|c| c.as_str())
-                .ok_or_else(make_syntax_err!("Malformed section header"))?;
+            else {
+                state.recover(Error::Syntax(String::from("Malformed section header")))?;
+                state.read_next_line();
+                return Ok(Section::Skipped);
+            };
+
+            state.section = Some(section_title);
 
             match section_title {
                 "General" => Ok(Section::General(parse_kv_section! {
                     |GeneralSection, state| {
                         "AudioFilename" => audio_filename: parse_string;
                         "AudioLeadIn" => audio_lead_in: parse_num;
+                        "AudioHash" => audio_hash: parse_string;
                         "PreviewTime" => preview_time: parse_num;
-                        "Countdown" => countdown: parse_bool;
-                        "SampleSet" => sample_set: parse_string;
+                        "Countdown" => countdown: parse_countdown;
+                        "CountdownOffset" => countdown_offset: parse_num;
+                        "SampleSet" => sample_set: parse_sample_set_name;
                         "StackLeniency" => stack_leniency: parse_num;
                         "Mode" => game_mode: parse_mode;
                         "LetterboxInBreaks" => letterbox_in_breaks: parse_bool;
@@ -384,6 +782,11 @@ impl OsuParser {
                         "StoryFireInFront" => story_fire_in_front: parse_bool;
                         "SpecialStyle" => special_style: parse_bool;
                         "EpilepsyWarning" => epilepsy_warning: parse_bool;
+                        "UseSkinSprites" => use_skin_sprites: parse_bool;
+                        "AlwaysShowPlayfield" => always_show_playfield: parse_bool;
+                        "OverlayPosition" => overlay_position: parse_overlay_position;
+                        "SkinPreference" => skin_preference: parse_string;
+                        "SamplesMatchPlaybackRate" => samples_match_playback_rate: parse_bool;
                     }
                 })),
 
@@ -423,11 +826,7 @@ impl OsuParser {
                     }
                 })),
 
-                "Events" => {
-                    // Just skipping this for now
-                    Self::skip_section(state);
-                    Ok(Section::Events)
-                }
+                "Events" => Self::parse_events(state).map(Section::Events),
 
                 "TimingPoints" => {
                     Self::parse_timing_points(state).map(|s| Section::TimingPoints(s))
@@ -437,10 +836,14 @@ impl OsuParser {
 
                 "Colours" => Self::parse_colours(state).map(|s| Section::Colours(s)),
 
-                _ => Err(Error::Syntax(format!(
-                    "Unknown section header {}",
-                    section_title
-                ))),
+                _ => {
+                    state.recover(Error::Syntax(format!(
+                        "Unknown section header {}",
+                        section_title
+                    )))?;
+                    Self::skip_section(state);
+                    Ok(Section::Skipped)
+                }
             }
         } else {
             Ok(Section::None)
@@ -448,13 +851,9 @@ impl OsuParser {
     }
 
     fn skip_section(state: &mut ParseState) {
-        lazy_static! {
-            static ref HEADER_RE: Regex = Regex::new(r"^\[([^\[\]]*)\]\s*$").unwrap();
-        }
-
         loop {
             match state.read_next_line() {
-                Some(l) if !HEADER_RE.is_match(l) => {}
+                Some(l) if !is_header(l) => {}
                 _ => break,
             }
         }
@@ -462,38 +861,47 @@ impl OsuParser {
 
     fn parse_version_string(state: &mut ParseState) -> Result<i32> {
         lazy_static! {
-            static ref RE: Regex = Regex::new(r"^osu file format v(\d+)$").unwrap();
+            static ref RE: Regex = Regex::new(r"^\s*osu file format v(\d+)\s*$").unwrap();
         }
 
         state
             .get_current_line()
-            .filter(|line| RE.is_match(line))
-            .and_then(|line| line[17..].parse::<i32>().ok())
+            .and_then(|line| RE.captures(line))
+            .and_then(|c| c[1].parse::<i32>().ok())
             .ok_or_else(make_syntax_err!("unable to parse version string"))
     }
 
-    fn parse_timing_points(state: &mut ParseState) -> Result<Vec<TimingPoint>> {
-        lazy_static! {
-            static ref HEADER_RE: Regex = Regex::new(r"^\[([^\[\]]*)\]\s*$").unwrap();
-        }
+    /// Only the offset and beat length are required, early versions omit the rest.
+    fn parse_timing_point(l: &str) -> Result<TimingPoint> {
+        let mut iter = l.split(",").map(|s| s.trim());
+
+        let offset: f32 = read_val!(iter, parse_num)?;
+        let ms_per_beat: f32 = read_val!(iter, parse_num)?;
+
+        Ok(TimingPoint {
+            offset,
+            ms_per_beat,
+            meter: match read_opt!(iter, parse_num, 4) {
+                0 => 4,
+                meter => meter,
+            },
+            sample_set: read_opt!(iter, parse_sample_set, SampleSet::Auto),
+            sample_index: read_opt!(iter, parse_num, 0),
+            volume: read_opt!(iter, parse_num, 100),
+            inherited: read_opt!(iter, parse_bool, ms_per_beat > 0.0),
+            // Bit 0 of the effects is kiai, bit 3 omits the first barline
+            kiai_mode: read_opt!(iter, parse_num::<i32>, 0) & 1 != 0,
+        })
+    }
 
+    fn parse_timing_points(state: &mut ParseState) -> Result<Vec<TimingPoint>> {
         let mut timing_points = Vec::with_capacity(100);
         loop {
             match state.read_next_line() {
-                Some(l) if !HEADER_RE.is_match(l) => {
-                    let timing_point = parse_into_struct!(",", TimingPoint, l; {
-                        offset: parse_num,
-                        ms_per_beat: parse_num,
-                        meter: parse_num,
-                        sample_set: parse_string,
-                        sample_index: parse_num,
-                        volume: parse_num,
-                        inherited: parse_bool,
-                        kiai_mode: parse_bool
-                    });
-
-                    timing_points.push(timing_point)
-                }
+                Some(l) if !is_header(l) => match Self::parse_timing_point(l) {
+                    Ok(timing_point) => timing_points.push(timing_point),
+                    Err(e) => state.recover(e)?,
+                },
                 _ => break,
             };
         }
@@ -511,23 +919,30 @@ impl OsuParser {
         let mut colours = Vec::with_capacity(10);
 
         loop {
-            match parse_kv_pair(state) {
-                Some((k, v)) if COLOR_RE.is_match(k) => {
-                    let n: i32 = parse_num(&k[5..])?;
-                    colours.push((n, parse_colour(v)?));
-                }
+            let result = match parse_kv_pair(state) {
+                Some((k, v)) if COLOR_RE.is_match(k) => parse_num::<i32>(&k[5..])
+                    .and_then(|n| parse_colour(v).map(|c| colours.push((n, c)))),
 
-                Some(("SliderBody", v)) => section.slider_body = parse_colour(v)?,
+                Some(("SliderBody", v)) => parse_colour(v).map(|c| section.slider_body = c),
 
                 Some(("SliderTrackOverride", v)) => {
-                    section.slider_track_override = parse_colour(v)?
+                    parse_colour(v).map(|c| section.slider_track_override = c)
                 }
 
-                Some(("SliderBorder", v)) => section.slider_border = parse_colour(v)?,
+                Some(("SliderBorder", v)) => parse_colour(v).map(|c| section.slider_border = c),
 
-                Some(_) => return Err(Error::Syntax(String::from("Unknown key value"))),
+                Some((k, v)) => {
+                    section.unknown.push((k.into(), v.into()));
+                    Ok(())
+                }
 
-                _ => break,
+                None if state.at_section_end() => break,
+
+                None => Err(Error::Syntax(String::from("Expected key: value pair"))),
+            };
+
+            if let Err(e) = result {
+                state.recover(e)?;
             }
         }
 
@@ -536,18 +951,31 @@ impl OsuParser {
         Ok(section)
     }
 
-    fn parse_hit_objects(state: &mut ParseState) -> Result<Vec<HitObject>> {
-        lazy_static! {
-            static ref HEADER_RE: Regex = Regex::new(r"^\[([^\[\]]*)\]\s*$").unwrap();
+    fn parse_events(state: &mut ParseState) -> Result<Vec<Event>> {
+        let mut events = Vec::new();
+
+        loop {
+            match state.read_next_line() {
+                Some(l) if !is_header(l) => match parse_event(l) {
+                    Ok(event) => events.push(event),
+                    Err(e) => state.recover(e)?,
+                },
+                _ => break,
+            }
         }
 
+        Ok(events)
+    }
+
+    fn parse_hit_objects(state: &mut ParseState) -> Result<Vec<HitObject>> {
         let mut hit_objects = Vec::with_capacity(100);
 
         loop {
             match state.read_next_line() {
-                Some(l) if !HEADER_RE.is_match(l) => {
-                    hit_objects.push(parse_hit_object(l)?);
-                }
+                Some(l) if !is_header(l) => match parse_hit_object(l) {
+                    Ok(hit_object) => hit_objects.push(hit_object),
+                    Err(e) => state.recover(e)?,
+                },
                 _ => break,
             }
         }
@@ -555,24 +983,473 @@ impl OsuParser {
         Ok(hit_objects)
     }
 
-    fn from_osu(&mut self, name: String) -> Difficulty {
-        let mut diff = Self::parse_difficulty(
-            String::from_utf8(self.files.get(&name).unwrap().clone())
-                .unwrap()
-                .as_str(),
-        )
-        .unwrap();
+    fn parse_osu(&mut self, name: String) -> Result<Difficulty> {
+        let bytes = self.assets.read(&name)?;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| Error::Message(format!("{}: file is not valid UTF-8", name)))?;
 
-        diff.audio_bytes = self
-            .files
-            .get(&diff.general.audio_filename)
-            .expect("Failed to retrieve audio from osz")
-            .clone();
+        let mut state = ParseState::new(&text).file(&name).lenient(self.lenient);
+        let mut diff = Self::parse_difficulty(&mut state)?;
+        self.warnings.append(&mut state.warnings);
+
+        if !self.assets.names().contains(&diff.general.audio_filename) {
+            return Err(Error::Message(format!(
+                "{}: audio file '{}' is missing",
+                name, diff.general.audio_filename
+            )));
+        }
+        diff.assets = Some(self.assets.clone());
 
         println!(
             "Parsed '{}[{}]'",
             diff.metadata.title, diff.metadata.version
         );
-        diff
+        Ok(diff)
+    }
+}
+
+/// Format version written by `OsuWriter`. Parsing already applied the quirks of older
+/// versions, so their maps are saved as this version.
+pub const LATEST_VERSION: i32 = 14;
+
+/// Writes difficulties back to `.osu` files and beatmap sets to `.osz` archives.
+pub struct OsuWriter;
+
+impl OsuWriter {
+    pub fn to_osu(diff: &Difficulty) -> String {
+        let mut out = String::new();
+        Self::write(diff, &mut out).expect("writing to a String can't fail");
+        out
+    }
+
+    pub fn write(diff: &Difficulty, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        writeln!(out, "osu file format v{}", LATEST_VERSION)?;
+
+        Self::write_general(&diff.general, out)?;
+        Self::write_editor(&diff.editor, out)?;
+        Self::write_metadata(&diff.metadata, out)?;
+        Self::write_difficulty(&diff.difficulty, out)?;
+
+        writeln!(out, "\n[Events]")?;
+        for event in &diff.events {
+            write_event(event, out)?;
+        }
+
+        writeln!(out, "\n[TimingPoints]")?;
+        for point in &diff.timing_points {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{}",
+                point.offset,
+                point.ms_per_beat,
+                point.meter,
+                point.sample_set as i32,
+                point.sample_index,
+                point.volume,
+                point.inherited as i32,
+                point.kiai_mode as i32
+            )?;
+        }
+
+        Self::write_colours(&diff.colours, out)?;
+
+        writeln!(out, "\n[HitObjects]")?;
+        for object in &diff.hit_objects {
+            write_hit_object(object, out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves a whole set, e.g. after editing, as an `.osz` archive.
+    pub fn to_osz(beatmap: &Beatmap, path: impl AsRef<Path>) -> Result<()> {
+        Self::write_osz(beatmap, File::create(path)?).map(|_| ())
+    }
+
+    /// Packs every difficulty together with the other files of its set.
+    pub fn write_osz<W: Write + Seek>(beatmap: &Beatmap, writer: W) -> Result<W> {
+        let mut zip = ZipWriter::new(writer);
+        let options = SimpleFileOptions::default();
+        let mut packed: Vec<&str> = Vec::new();
+
+        for diff in &beatmap.difficulties {
+            zip.start_file(Self::file_name(diff), options)?;
+            zip.write_all(Self::to_osu(diff).as_bytes())?;
+
+            let Some(assets) = &diff.assets else {
+                continue;
+            };
+
+            for name in assets.names() {
+                if name.ends_with(".osu") || packed.contains(&name.as_str()) {
+                    continue;
+                }
+
+                zip.start_file(name, options)?;
+                zip.write_all(&assets.read(name)?)?;
+                packed.push(name);
+            }
+        }
+
+        Ok(zip.finish()?)
+    }
+
+    /// The name the official client gives a difficulty, `Artist - Title (Creator) [Version].osu`.
+    pub fn file_name(diff: &Difficulty) -> String {
+        let metadata = &diff.metadata;
+        format!(
+            "{} - {} ({}) [{}].osu",
+            metadata.artist, metadata.title, metadata.creator, metadata.version
+        )
+        .chars()
+        .filter(|c| !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|'))
+        .collect()
+    }
+
+    fn write_general(general: &GeneralSection, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        writeln!(out, "\n[General]")?;
+        writeln!(out, "AudioFilename: {}", general.audio_filename)?;
+        writeln!(out, "AudioLeadIn: {}", general.audio_lead_in)?;
+        if !general.audio_hash.is_empty() {
+            writeln!(out, "AudioHash: {}", general.audio_hash)?;
+        }
+        writeln!(out, "PreviewTime: {}", general.preview_time)?;
+        writeln!(out, "Countdown: {}", general.countdown as i32)?;
+        writeln!(out, "CountdownOffset: {}", general.countdown_offset)?;
+        writeln!(
+            out,
+            "SampleSet: {}",
+            match general.sample_set {
+                SampleSet::Auto | SampleSet::Normal => "Normal",
+                SampleSet::Soft => "Soft",
+                SampleSet::Drum => "Drum",
+            }
+        )?;
+        writeln!(out, "StackLeniency: {}", general.stack_leniency)?;
+        writeln!(out, "Mode: {}", general.game_mode as i32)?;
+        writeln!(
+            out,
+            "LetterboxInBreaks: {}",
+            general.letterbox_in_breaks as i32
+        )?;
+        writeln!(out, "UseSkinSprites: {}", general.use_skin_sprites as i32)?;
+        writeln!(
+            out,
+            "AlwaysShowPlayfield: {}",
+            general.always_show_playfield as i32
+        )?;
+        writeln!(
+            out,
+            "OverlayPosition: {}",
+            match general.overlay_position {
+                OverlayPosition::NoChange => "NoChange",
+                OverlayPosition::Below => "Below",
+                OverlayPosition::Above => "Above",
+            }
+        )?;
+        if !general.skin_preference.is_empty() {
+            writeln!(out, "SkinPreference: {}", general.skin_preference)?;
+        }
+        writeln!(out, "EpilepsyWarning: {}", general.epilepsy_warning as i32)?;
+        writeln!(out, "SpecialStyle: {}", general.special_style as i32)?;
+        writeln!(
+            out,
+            "WidescreenStoryboard: {}",
+            general.widescreen_storyboard as i32
+        )?;
+        writeln!(
+            out,
+            "StoryFireInFront: {}",
+            general.story_fire_in_front as i32
+        )?;
+        writeln!(
+            out,
+            "SamplesMatchPlaybackRate: {}",
+            general.samples_match_playback_rate as i32
+        )?;
+        write_unknown(&general.unknown, out)
+    }
+
+    fn write_editor(editor: &EditorSection, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        writeln!(out, "\n[Editor]")?;
+        if !editor.bookmarks.is_empty() {
+            writeln!(out, "Bookmarks: {}", join(&editor.bookmarks, ","))?;
+        }
+        writeln!(out, "DistanceSpacing: {}", editor.distance_spacing)?;
+        writeln!(out, "BeatDivisor: {}", editor.beat_divisor)?;
+        writeln!(out, "GridSize: {}", editor.grid_size)?;
+        writeln!(out, "TimelineZoom: {}", editor.timeline_zoom)?;
+        write_unknown(&editor.unknown, out)
+    }
+
+    fn write_metadata(
+        metadata: &MetadataSection,
+        out: &mut impl std::fmt::Write,
+    ) -> std::fmt::Result {
+        writeln!(out, "\n[Metadata]")?;
+        writeln!(out, "Title:{}", metadata.title)?;
+        writeln!(out, "TitleUnicode:{}", metadata.title_unicode)?;
+        writeln!(out, "Artist:{}", metadata.artist)?;
+        writeln!(out, "ArtistUnicode:{}", metadata.artist_unicode)?;
+        writeln!(out, "Creator:{}", metadata.creator)?;
+        writeln!(out, "Version:{}", metadata.version)?;
+        writeln!(out, "Source:{}", metadata.source)?;
+        writeln!(out, "Tags:{}", metadata.tags.join(" "))?;
+        writeln!(out, "BeatmapID:{}", metadata.beatmap_id)?;
+        writeln!(out, "BeatmapSetID:{}", metadata.beatmap_set_id)?;
+        write_unknown(&metadata.unknown, out)
+    }
+
+    fn write_difficulty(
+        difficulty: &DifficultySection,
+        out: &mut impl std::fmt::Write,
+    ) -> std::fmt::Result {
+        writeln!(out, "\n[Difficulty]")?;
+        writeln!(out, "HPDrainRate:{}", difficulty.hp_drain_rate)?;
+        writeln!(out, "CircleSize:{}", difficulty.circle_size)?;
+        writeln!(out, "OverallDifficulty:{}", difficulty.overall_difficulty)?;
+        writeln!(out, "ApproachRate:{}", difficulty.approach_rate)?;
+        writeln!(out, "SliderMultiplier:{}", difficulty.slider_multiplier)?;
+        writeln!(out, "SliderTickRate:{}", difficulty.slider_tick_rate)?;
+        write_unknown(&difficulty.unknown, out)
+    }
+
+    fn write_colours(colours: &ColoursSection, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        writeln!(out, "\n[Colours]")?;
+        for (i, Colour(r, g, b)) in colours.colours.iter().enumerate() {
+            writeln!(out, "Combo{} : {},{},{}", i + 1, r, g, b)?;
+        }
+
+        // The model can't tell an absent colour from black, and both parse back the same
+        for (key, Colour(r, g, b)) in [
+            ("SliderTrackOverride", &colours.slider_track_override),
+            ("SliderBody", &colours.slider_body),
+            ("SliderBorder", &colours.slider_border),
+        ] {
+            if (*r, *g, *b) != (0, 0, 0) {
+                writeln!(out, "{} : {},{},{}", key, r, g, b)?;
+            }
+        }
+
+        write_unknown(&colours.unknown, out)
+    }
+}
+
+fn write_unknown(unknown: &[(String, String)], out: &mut impl std::fmt::Write) -> std::fmt::Result {
+    for (key, value) in unknown {
+        writeln!(out, "{}: {}", key, value)?;
+    }
+    Ok(())
+}
+
+fn join<T: Display>(values: &[T], sep: &str) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+fn write_event(event: &Event, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+    match event {
+        Event::Background { filename, x, y } => writeln!(out, "0,0,\"{}\",{},{}", filename, x, y),
+        Event::Video {
+            start_time,
+            filename,
+            x,
+            y,
+        } => writeln!(out, "Video,{},\"{}\",{},{}", start_time, filename, x, y),
+        Event::Break {
+            start_time,
+            end_time,
+        } => writeln!(out, "2,{},{}", start_time, end_time),
+        Event::Other(line) => writeln!(out, "{}", line),
+    }
+}
+
+fn write_hit_object(object: &HitObject, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+    let object_type = |kind: i32, new_combo: bool, color_skip: i32| {
+        kind | (new_combo as i32) << 2 | color_skip << 4
+    };
+
+    match object {
+        HitObject::HitCircle(o) => writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            o.x,
+            o.y,
+            o.time,
+            object_type(1, o.new_combo, o.color_skip),
+            o.hitsound.bits(),
+            extras_string(&o.extras)
+        ),
+
+        HitObject::Slider(o) => {
+            let slider_type = match o.slider_type {
+                SliderType::Linear => "L",
+                SliderType::Bezier => "B",
+                SliderType::Perfect => "P",
+                SliderType::Catmull => "C",
+            };
+            let curve: String = o
+                .curve_points
+                .iter()
+                .map(|(x, y)| format!("|{}:{}", x, y))
+                .collect();
+            let edge_additions: Vec<String> = o
+                .edge_additions
+                .iter()
+                .map(|&(normal, addition)| format!("{}:{}", normal as i32, addition as i32))
+                .collect();
+
+            writeln!(
+                out,
+                "{},{},{},{},{},{}{},{},{},{},{},{}",
+                o.x,
+                o.y,
+                o.time,
+                object_type(2, o.new_combo, o.color_skip),
+                o.hitsound.bits(),
+                slider_type,
+                curve,
+                o.repeat,
+                o.pixel_length,
+                join(
+                    &o.edge_hitsounds
+                        .iter()
+                        .map(|h| h.bits())
+                        .collect::<Vec<_>>(),
+                    "|"
+                ),
+                edge_additions.join("|"),
+                extras_string(&o.extras)
+            )
+        }
+
+        HitObject::Spinner(o) => writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            o.x,
+            o.y,
+            o.time,
+            object_type(8, o.new_combo, o.color_skip),
+            o.hitsound.bits(),
+            o.end_time,
+            extras_string(&o.extras)
+        ),
+
+        HitObject::HoldNote(o) => writeln!(
+            out,
+            "{},{},{},{},{},{}:{}",
+            o.x,
+            o.y,
+            o.time,
+            object_type(128, o.new_combo, o.color_skip),
+            o.hitsound.bits(),
+            o.end_time,
+            extras_string(&o.extras)
+        ),
+    }
+}
+
+fn extras_string(extras: &HitObjectExtras) -> String {
+    format!(
+        "{}:{}:{}:{}:{}",
+        extras.sample_set as i32,
+        extras.addition_set as i32,
+        extras.custom_index,
+        extras.sample_volume,
+        extras.filename
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same(a: &Difficulty, b: &Difficulty) {
+        assert_eq!(a.version, b.version);
+        assert_eq!(a.general, b.general);
+        assert_eq!(a.editor, b.editor);
+        assert_eq!(a.metadata, b.metadata);
+        assert_eq!(a.difficulty, b.difficulty);
+        assert_eq!(a.events, b.events);
+        assert_eq!(a.timing_points, b.timing_points);
+        assert_eq!(a.colours, b.colours);
+        assert_eq!(a.hit_objects, b.hit_objects);
+    }
+
+    #[test]
+    fn clover_round_trips() {
+        let original = OsuParser::from_osz("maps/clover.osz", false).unwrap();
+
+        for diff in &original.difficulties {
+            let text = OsuWriter::to_osu(diff);
+            let mut state = ParseState::new(&text);
+            let reparsed = OsuParser::parse_difficulty(&mut state).unwrap();
+            assert_same(diff, &reparsed);
+        }
+
+        let packed = OsuWriter::write_osz(&original, Cursor::new(Vec::new())).unwrap();
+        let unpacked = OsuParser::from_bytes(packed.into_inner(), false).unwrap();
+
+        assert_eq!(original.difficulties.len(), unpacked.difficulties.len());
+        for (a, b) in original.difficulties.iter().zip(&unpacked.difficulties) {
+            assert_same(a, b);
+            assert_eq!(a.audio().unwrap(), b.audio().unwrap());
+        }
+    }
+
+    #[test]
+    fn comments_in_key_value_sections_are_skipped() {
+        let text = "osu file format v14\n\n[Difficulty]\n// Picked by ear\nOverallDifficulty:7\n";
+        let mut state = ParseState::new(text);
+        let map = OsuParser::parse_difficulty(&mut state).unwrap();
+
+        assert_eq!(map.difficulty.overall_difficulty, 7.);
+        assert!(map.difficulty.unknown.is_empty());
+    }
+
+    fn parse_version(version: u32) -> Difficulty {
+        let text = format!(
+            "osu file format v{version}\n\n\
+            [General]\nPreviewTime: 500\n\n\
+            [Difficulty]\nOverallDifficulty:7\n\n\
+            [Events]\n2,2000,3000\n\n\
+            [TimingPoints]\n1000,500,4,2,0,60,1,0\n\n\
+            [HitObjects]\n256,192,1000,1,0,0:0:0:0:\n"
+        );
+        let mut state = ParseState::new(&text);
+        OsuParser::parse_difficulty(&mut state).unwrap()
+    }
+
+    #[test]
+    fn old_versions_are_shifted_late() {
+        let old = parse_version(4);
+        assert_eq!(old.general.preview_time, 524);
+        assert_eq!(old.timing_points[0].offset, 1024.);
+        assert_eq!(old.hit_objects[0].time(), 1024);
+        assert_eq!(
+            old.events[0],
+            Event::Break {
+                start_time: 2024,
+                end_time: 3024
+            }
+        );
+
+        let new = parse_version(5);
+        assert_eq!(new.general.preview_time, 500);
+        assert_eq!(new.timing_points[0].offset, 1000.);
+        assert_eq!(new.hit_objects[0].time(), 1000);
+    }
+
+    #[test]
+    fn approach_rate_defaults_to_overall_difficulty() {
+        assert_eq!(parse_version(14).difficulty.approach_rate, 7.);
+
+        let text = "osu file format v14\n\n[Difficulty]\nOverallDifficulty:7\nApproachRate:9\n";
+        let mut state = ParseState::new(text);
+        let map = OsuParser::parse_difficulty(&mut state).unwrap();
+        assert_eq!(map.difficulty.approach_rate, 9.);
     }
 }
@@ -0,0 +1,978 @@
+//! zoku's own map format.
+//!
+//! A `.zoku` file is a TOML header followed by an `[objects]` line and a compact object
+//! list, one object per line:
+//!
+//! ```text
+//! format = 1
+//!
+//! [metadata]
+//! title = "Harumachi Clover"
+//! version = "Expert"
+//!
+//! [general]
+//! countdown = "none"
+//! sample_set = "soft"
+//!
+//! [[breaks]]
+//! start = 40000.0
+//! end = 45000.0
+//!
+//! [[audio]]
+//! file = "audio.mp3"
+//!
+//! [[timing]]
+//! time = -1250.0
+//! beat_length = 352.94
+//!
+//! [objects]
+//! 161 circle 374 156 combo=0
+//! 2279 slider 183 147 perfect(117:119,71:129) 1 105.49 ar=9.5
+//! 2985 slider 181 320 bezier(235:281,284:288)/linear(300:300) 2 180
+//! 9000 spinner 11000 sampleset=drum
+//! 12000 hold 2 12500
+//! ```
+//!
+//! Unlike `.osu` it supports an approach rate per object, sliders made of any mix of
+//! curve kinds and several audio tracks layered over each other.
+
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::content::beatmap::{
+    Colour, Countdown, Difficulty, DifficultySection, Event, GeneralSection, HitCircle, HitObject,
+    HitObjectExtras, HitSound, HoldNote, MetadataSection, SampleSet, Slider, SliderType, Spinner,
+    TimingPoint,
+};
+
+/// Newest format this module reads, and the one it writes.
+pub const FORMAT_VERSION: u32 = 1;
+
+const OBJECTS_HEADER: &str = "[objects]";
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Header(toml::de::Error),
+    MissingObjects,
+    UnsupportedVersion(u32),
+    Object { line: usize, message: String },
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(formatter, "I/O error: {}", e),
+            Error::Header(e) => write!(formatter, "Invalid header: {}", e),
+            Error::MissingObjects => write!(formatter, "Missing {} section", OBJECTS_HEADER),
+            Error::UnsupportedVersion(v) => write!(
+                formatter,
+                "Unsupported format version {} (newest is {})",
+                v, FORMAT_VERSION
+            ),
+            Error::Object { line, message } => write!(formatter, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Header(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ZokuMap {
+    pub format: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colours: Vec<[u8; 3]>,
+    pub metadata: Metadata,
+    #[serde(default)]
+    pub general: General,
+    #[serde(default)]
+    pub difficulty: Settings,
+    pub audio: Vec<AudioTrack>,
+    pub timing: Vec<TimingSection>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breaks: Vec<Break>,
+    #[serde(skip)]
+    pub objects: Vec<Object>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    pub title: String,
+    pub title_unicode: String,
+    pub artist: String,
+    pub artist_unicode: String,
+    pub creator: String,
+    pub version: String,
+    pub source: String,
+    pub tags: Vec<String>,
+    pub preview_time: f64,
+}
+
+/// Mirrors `Countdown` in the header.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Countdown", rename_all = "lowercase")]
+enum CountdownDef {
+    None,
+    Normal,
+    Half,
+    Double,
+}
+
+/// Mirrors `SampleSet` in the header.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SampleSet", rename_all = "lowercase")]
+enum SampleSetDef {
+    Auto,
+    Normal,
+    Soft,
+    Drum,
+}
+
+fn is_auto(set: &SampleSet) -> bool {
+    *set == SampleSet::Auto
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct General {
+    /// Silence before the song starts, in ms.
+    pub audio_lead_in: f64,
+    #[serde(with = "CountdownDef")]
+    pub countdown: Countdown,
+    /// Beats the countdown is moved earlier by, so it ends that many beats further from the
+    /// first object.
+    pub countdown_offset: i32,
+    pub stack_leniency: f64,
+    /// Used by objects and timing sections that don't pick one.
+    #[serde(with = "SampleSetDef")]
+    pub sample_set: SampleSet,
+}
+
+/// Unlike `.osu`, a map without a countdown setting has none.
+impl Default for General {
+    fn default() -> Self {
+        General {
+            audio_lead_in: 0.,
+            countdown: Countdown::None,
+            countdown_offset: 0,
+            stack_leniency: 0.7,
+            sample_set: SampleSet::Normal,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub hp_drain_rate: f64,
+    pub circle_size: f64,
+    pub overall_difficulty: f64,
+    pub approach_rate: f64,
+    pub slider_multiplier: f64,
+    pub slider_tick_rate: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            hp_drain_rate: 5.0,
+            circle_size: 5.0,
+            overall_difficulty: 5.0,
+            approach_rate: 5.0,
+            slider_multiplier: 1.4,
+            slider_tick_rate: 1.0,
+        }
+    }
+}
+
+/// Tracks all play at once, e.g. a song split into stems or a separate hitsound layer.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct AudioTrack {
+    pub file: String,
+    /// Map time in ms at which the track starts.
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default = "full_volume")]
+    pub volume: f64,
+}
+
+fn full_volume() -> f64 {
+    1.0
+}
+
+/// A change in timing; any field left out carries over from the previous section.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimingSection {
+    pub time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat_length: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meter: Option<u32>,
+    /// Slider velocity multiplier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<f64>,
+    /// Hitsound volume from 0 to 100.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kiai: Option<bool>,
+    #[serde(with = "SampleSetDef", default, skip_serializing_if = "is_auto")]
+    pub sample_set: SampleSet,
+    /// Custom sample index, 0 for the skin's samples.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_index: Option<i32>,
+}
+
+/// A span without objects where health doesn't drain.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Break {
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub time: f32,
+    pub kind: ObjectKind,
+    pub new_combo: bool,
+    pub colour_skip: u32,
+    /// Overrides the map's approach rate for this object.
+    pub approach_rate: Option<f32>,
+    pub hitsound: HitSound,
+    /// Set of the hit normal.
+    pub sample_set: SampleSet,
+    /// Set of the whistle, finish and clap.
+    pub addition_set: SampleSet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectKind {
+    Circle {
+        position: (f32, f32),
+    },
+    Slider {
+        position: (f32, f32),
+        curve: Vec<CurveSegment>,
+        slides: u32,
+        length: f32,
+    },
+    Spinner {
+        end_time: f32,
+    },
+    /// A mania hold note, in one of `circle_size` columns counted from the left.
+    Hold {
+        column: u32,
+        end_time: f32,
+    },
+}
+
+/// Part of a slider path. It starts where the previous segment, or the slider head, ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurveSegment {
+    pub kind: CurveKind,
+    pub points: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveKind {
+    Linear,
+    Bezier,
+    Perfect,
+    Catmull,
+}
+
+impl CurveKind {
+    fn name(self) -> &'static str {
+        match self {
+            CurveKind::Linear => "linear",
+            CurveKind::Bezier => "bezier",
+            CurveKind::Perfect => "perfect",
+            CurveKind::Catmull => "catmull",
+        }
+    }
+}
+
+pub struct ZokuParser;
+
+impl ZokuParser {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ZokuMap> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(input: &str) -> Result<ZokuMap> {
+        let mut header_end = None;
+        let mut offset = 0;
+        for (i, line) in input.split_inclusive('\n').enumerate() {
+            if line.trim() == OBJECTS_HEADER {
+                header_end = Some((i + 1, offset, offset + line.len()));
+                break;
+            }
+            offset += line.len();
+        }
+        let (header_lines, header_len, objects_start) = header_end.ok_or(Error::MissingObjects)?;
+
+        let mut map: ZokuMap = toml::from_str(&input[..header_len])?;
+        if map.format > FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(map.format));
+        }
+
+        for (i, line) in input[objects_start..].lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let object = parse_object(line).map_err(|message| Error::Object {
+                line: header_lines + i + 1,
+                message,
+            })?;
+            map.objects.push(object);
+        }
+
+        Ok(map)
+    }
+}
+
+fn parse_value<T: FromStr>(s: Option<&str>, name: &str) -> std::result::Result<T, String> {
+    let s = s.ok_or_else(|| format!("missing {}", name))?;
+    s.parse()
+        .map_err(|_| format!("expected {}, found '{}'", name, s))
+}
+
+fn parse_sample_set(s: &str) -> std::result::Result<SampleSet, String> {
+    match s {
+        "auto" => Ok(SampleSet::Auto),
+        "normal" => Ok(SampleSet::Normal),
+        "soft" => Ok(SampleSet::Soft),
+        "drum" => Ok(SampleSet::Drum),
+        _ => Err(format!("unknown sample set '{}'", s)),
+    }
+}
+
+fn sample_set_name(set: SampleSet) -> &'static str {
+    match set {
+        SampleSet::Auto => "auto",
+        SampleSet::Normal => "normal",
+        SampleSet::Soft => "soft",
+        SampleSet::Drum => "drum",
+    }
+}
+
+fn parse_point(s: &str) -> std::result::Result<(f32, f32), String> {
+    let (x, y) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected x:y, found '{}'", s))?;
+    Ok((parse_value(Some(x), "x")?, parse_value(Some(y), "y")?))
+}
+
+fn parse_curve(s: &str) -> std::result::Result<Vec<CurveSegment>, String> {
+    s.split('/')
+        .map(|segment| {
+            let (kind, points) = segment
+                .strip_suffix(')')
+                .and_then(|s| s.split_once('('))
+                .ok_or_else(|| format!("expected kind(x:y,...), found '{}'", segment))?;
+
+            let kind = match kind {
+                "linear" => CurveKind::Linear,
+                "bezier" => CurveKind::Bezier,
+                "perfect" => CurveKind::Perfect,
+                "catmull" => CurveKind::Catmull,
+                _ => return Err(format!("unknown curve kind '{}'", kind)),
+            };
+
+            Ok(CurveSegment {
+                kind,
+                points: points
+                    .split(',')
+                    .filter(|p| !p.is_empty())
+                    .map(parse_point)
+                    .collect::<std::result::Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
+
+fn parse_object(line: &str) -> std::result::Result<Object, String> {
+    let mut fields = line.split_whitespace();
+
+    let time = parse_value(fields.next(), "time")?;
+    let kind = match fields.next() {
+        Some("circle") => ObjectKind::Circle {
+            position: (
+                parse_value(fields.next(), "x")?,
+                parse_value(fields.next(), "y")?,
+            ),
+        },
+        Some("slider") => ObjectKind::Slider {
+            position: (
+                parse_value(fields.next(), "x")?,
+                parse_value(fields.next(), "y")?,
+            ),
+            curve: parse_curve(fields.next().ok_or("missing curve")?)?,
+            slides: parse_value(fields.next(), "slide count")?,
+            length: parse_value(fields.next(), "length")?,
+        },
+        Some("spinner") => ObjectKind::Spinner {
+            end_time: parse_value(fields.next(), "end time")?,
+        },
+        Some("hold") => ObjectKind::Hold {
+            column: parse_value(fields.next(), "column")?,
+            end_time: parse_value(fields.next(), "end time")?,
+        },
+        Some(kind) => return Err(format!("unknown object kind '{}'", kind)),
+        None => return Err(String::from("missing object kind")),
+    };
+
+    let mut object = Object {
+        time,
+        kind,
+        new_combo: false,
+        colour_skip: 0,
+        approach_rate: None,
+        hitsound: HitSound::empty(),
+        sample_set: SampleSet::Auto,
+        addition_set: SampleSet::Auto,
+    };
+
+    for attribute in fields {
+        let (key, value) = attribute
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, found '{}'", attribute))?;
+
+        match key {
+            "combo" => {
+                object.new_combo = true;
+                object.colour_skip = parse_value(Some(value), "colour skip")?;
+            }
+            "ar" => object.approach_rate = Some(parse_value(Some(value), "approach rate")?),
+            "hitsound" => {
+                object.hitsound =
+                    HitSound::from_bits_truncate(parse_value(Some(value), "hitsound")?)
+            }
+            "sampleset" => object.sample_set = parse_sample_set(value)?,
+            "additionset" => object.addition_set = parse_sample_set(value)?,
+            _ => return Err(format!("unknown attribute '{}'", key)),
+        }
+    }
+
+    Ok(object)
+}
+
+pub struct ZokuWriter;
+
+impl ZokuWriter {
+    pub fn to_zoku(map: &ZokuMap) -> String {
+        let mut out = toml::to_string(map).expect("zoku header is always valid TOML");
+
+        out.push('\n');
+        out.push_str(OBJECTS_HEADER);
+        out.push('\n');
+        for object in &map.objects {
+            out.push_str(&object_line(object));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    pub fn to_file(map: &ZokuMap, path: impl AsRef<Path>) -> Result<()> {
+        Ok(std::fs::write(path, Self::to_zoku(map))?)
+    }
+}
+
+fn object_line(object: &Object) -> String {
+    let point = |(x, y): (f32, f32)| format!("{}:{}", x, y);
+
+    let mut line = match &object.kind {
+        ObjectKind::Circle { position } => {
+            format!("{} circle {} {}", object.time, position.0, position.1)
+        }
+        ObjectKind::Slider {
+            position,
+            curve,
+            slides,
+            length,
+        } => {
+            let curve = curve
+                .iter()
+                .map(|segment| {
+                    let points: Vec<String> = segment.points.iter().copied().map(point).collect();
+                    format!("{}({})", segment.kind.name(), points.join(","))
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            format!(
+                "{} slider {} {} {} {} {}",
+                object.time, position.0, position.1, curve, slides, length
+            )
+        }
+        ObjectKind::Spinner { end_time } => format!("{} spinner {}", object.time, end_time),
+        ObjectKind::Hold { column, end_time } => {
+            format!("{} hold {} {}", object.time, column, end_time)
+        }
+    };
+
+    if object.new_combo {
+        line += &format!(" combo={}", object.colour_skip);
+    }
+    if let Some(ar) = object.approach_rate {
+        line += &format!(" ar={}", ar);
+    }
+    if !object.hitsound.is_empty() {
+        line += &format!(" hitsound={}", object.hitsound.bits());
+    }
+    if object.sample_set != SampleSet::Auto {
+        line += &format!(" sampleset={}", sample_set_name(object.sample_set));
+    }
+    if object.addition_set != SampleSet::Auto {
+        line += &format!(" additionset={}", sample_set_name(object.addition_set));
+    }
+
+    line
+}
+
+impl From<&Difficulty> for ZokuMap {
+    fn from(diff: &Difficulty) -> Self {
+        let metadata = &diff.metadata;
+        let difficulty = &diff.difficulty;
+
+        ZokuMap {
+            format: FORMAT_VERSION,
+            colours: diff
+                .colours
+                .colours
+                .iter()
+                .map(|c| [c.0 as u8, c.1 as u8, c.2 as u8])
+                .collect(),
+            metadata: Metadata {
+                title: metadata.title.clone(),
+                title_unicode: metadata.title_unicode.clone(),
+                artist: metadata.artist.clone(),
+                artist_unicode: metadata.artist_unicode.clone(),
+                creator: metadata.creator.clone(),
+                version: metadata.version.clone(),
+                source: metadata.source.clone(),
+                tags: metadata.tags.clone(),
+                preview_time: diff.general.preview_time as f64,
+            },
+            general: General {
+                audio_lead_in: diff.general.audio_lead_in as f64,
+                countdown: diff.general.countdown,
+                countdown_offset: diff.general.countdown_offset,
+                stack_leniency: widen(diff.general.stack_leniency),
+                sample_set: diff.general.sample_set,
+            },
+            difficulty: Settings {
+                hp_drain_rate: widen(difficulty.hp_drain_rate),
+                circle_size: widen(difficulty.circle_size),
+                overall_difficulty: widen(difficulty.overall_difficulty),
+                approach_rate: widen(difficulty.approach_rate),
+                slider_multiplier: widen(difficulty.slider_multiplier),
+                slider_tick_rate: widen(difficulty.slider_tick_rate),
+            },
+            audio: vec![AudioTrack {
+                file: diff.general.audio_filename.clone(),
+                offset: 0.,
+                volume: 1.,
+            }],
+            timing: diff
+                .timing_points
+                .iter()
+                .map(|point| {
+                    // Negative beat lengths are osu!'s inherited points, which only set velocity
+                    let (beat_length, meter, velocity) = if point.ms_per_beat > 0. {
+                        (
+                            Some(widen(point.ms_per_beat)),
+                            Some(point.meter as u32),
+                            None,
+                        )
+                    } else {
+                        (None, None, Some(-100. / widen(point.ms_per_beat)))
+                    };
+
+                    TimingSection {
+                        time: widen(point.offset),
+                        beat_length,
+                        meter,
+                        velocity,
+                        volume: Some(point.volume as u32),
+                        kiai: Some(point.kiai_mode),
+                        sample_set: point.sample_set,
+                        sample_index: Some(point.sample_index),
+                    }
+                })
+                .collect(),
+            breaks: diff
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Break {
+                        start_time,
+                        end_time,
+                    } => Some(Break {
+                        start: *start_time as f64,
+                        end: *end_time as f64,
+                    }),
+                    _ => None,
+                })
+                .collect(),
+            objects: diff
+                .hit_objects
+                .iter()
+                .map(|o| convert_object(o, difficulty.circle_size))
+                .collect(),
+        }
+    }
+}
+
+/// Goes through the shortest decimal form so e.g. `3.8f32` is written as `3.8`,
+/// not `3.799999952316284`.
+fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+/// The mania column an x position falls in, for `keys` columns across the 512 wide playfield.
+fn column_of(x: i32, keys: f32) -> u32 {
+    let keys = keys.max(1.) as i32;
+    (x * keys / 512).clamp(0, keys - 1) as u32
+}
+
+/// The x position osu! uses for the middle of a mania column.
+fn column_x(column: u32, keys: f32) -> i32 {
+    let keys = keys.max(1.) as i32;
+    (512 * column as i32 + 256) / keys
+}
+
+fn convert_object(object: &HitObject, keys: f32) -> Object {
+    let (x, y) = object.position();
+    let kind = match object {
+        HitObject::HitCircle(_) => ObjectKind::Circle {
+            position: (x as f32, y as f32),
+        },
+        HitObject::Slider(o) => ObjectKind::Slider {
+            position: (x as f32, y as f32),
+            curve: convert_curve(&o.slider_type, &o.curve_points),
+            slides: o.repeat as u32,
+            length: o.pixel_length,
+        },
+        HitObject::Spinner(o) => ObjectKind::Spinner {
+            end_time: o.end_time as f32,
+        },
+        HitObject::HoldNote(o) => ObjectKind::Hold {
+            column: column_of(x, keys),
+            end_time: o.end_time as f32,
+        },
+    };
+
+    Object {
+        time: object.time() as f32,
+        kind,
+        new_combo: object.new_combo(),
+        colour_skip: object.color_skip() as u32,
+        approach_rate: None,
+        hitsound: object.hitsound(),
+        sample_set: object.extras().sample_set,
+        addition_set: object.extras().addition_set,
+    }
+}
+
+/// osu! bezier sliders mark the start of a new segment by repeating a point.
+fn convert_curve(slider_type: &SliderType, points: &[(i32, i32)]) -> Vec<CurveSegment> {
+    let kind = match slider_type {
+        SliderType::Linear => CurveKind::Linear,
+        SliderType::Bezier => CurveKind::Bezier,
+        SliderType::Perfect => CurveKind::Perfect,
+        SliderType::Catmull => CurveKind::Catmull,
+    };
+
+    let mut segments = vec![CurveSegment {
+        kind,
+        points: Vec::new(),
+    }];
+
+    for (i, &(x, y)) in points.iter().enumerate() {
+        if kind == CurveKind::Bezier && i > 0 && points[i - 1] == (x, y) {
+            segments.push(CurveSegment {
+                kind,
+                points: Vec::new(),
+            });
+            continue;
+        }
+
+        segments
+            .last_mut()
+            .unwrap()
+            .points
+            .push((x as f32, y as f32));
+    }
+
+    segments.retain(|s| !s.points.is_empty());
+    segments
+}
+
+/// Lossy, for playing zoku maps with the osu! model: per-object approach rates and every
+/// audio track but the first are dropped, and mixed curves become a single bezier.
+impl From<&ZokuMap> for Difficulty {
+    fn from(map: &ZokuMap) -> Self {
+        let metadata = &map.metadata;
+        let settings = &map.difficulty;
+
+        let mut diff = Difficulty {
+            version: super::osu::LATEST_VERSION,
+            general: GeneralSection {
+                audio_filename: map
+                    .audio
+                    .first()
+                    .map(|track| track.file.clone())
+                    .unwrap_or_default(),
+                preview_time: metadata.preview_time as i32,
+                audio_lead_in: map.general.audio_lead_in as i32,
+                countdown: map.general.countdown,
+                countdown_offset: map.general.countdown_offset,
+                stack_leniency: map.general.stack_leniency as f32,
+                sample_set: map.general.sample_set,
+                ..Default::default()
+            },
+            metadata: MetadataSection {
+                title: metadata.title.clone(),
+                title_unicode: metadata.title_unicode.clone(),
+                artist: metadata.artist.clone(),
+                artist_unicode: metadata.artist_unicode.clone(),
+                creator: metadata.creator.clone(),
+                version: metadata.version.clone(),
+                source: metadata.source.clone(),
+                tags: metadata.tags.clone(),
+                ..Default::default()
+            },
+            difficulty: DifficultySection {
+                hp_drain_rate: settings.hp_drain_rate as f32,
+                circle_size: settings.circle_size as f32,
+                overall_difficulty: settings.overall_difficulty as f32,
+                approach_rate: settings.approach_rate as f32,
+                slider_multiplier: settings.slider_multiplier as f32,
+                slider_tick_rate: settings.slider_tick_rate as f32,
+                ..Default::default()
+            },
+            events: map
+                .breaks
+                .iter()
+                .map(|b| Event::Break {
+                    start_time: b.start as i32,
+                    end_time: b.end as i32,
+                })
+                .collect(),
+            hit_objects: map
+                .objects
+                .iter()
+                .map(|o| restore_object(o, settings.circle_size as f32))
+                .collect(),
+            ..Default::default()
+        };
+
+        diff.colours.colours = map
+            .colours
+            .iter()
+            .map(|[r, g, b]| Colour(*r as i32, *g as i32, *b as i32))
+            .collect();
+
+        let (mut meter, mut volume, mut kiai, mut sample_set, mut sample_index) =
+            (4, 100, false, SampleSet::Auto, 0);
+        for section in &map.timing {
+            meter = section.meter.unwrap_or(meter);
+            volume = section.volume.unwrap_or(volume);
+            kiai = section.kiai.unwrap_or(kiai);
+            sample_set = section.sample_set.or(sample_set);
+            sample_index = section.sample_index.unwrap_or(sample_index);
+
+            let point = |ms_per_beat: f64, inherited: bool| TimingPoint {
+                offset: section.time as f32,
+                ms_per_beat: ms_per_beat as f32,
+                meter: meter as i32,
+                sample_set,
+                sample_index,
+                volume: volume as i32,
+                inherited,
+                kiai_mode: kiai,
+            };
+
+            if let Some(beat_length) = section.beat_length {
+                diff.timing_points.push(point(beat_length, true));
+            }
+            if let Some(velocity) = section.velocity {
+                diff.timing_points.push(point(-100. / velocity, false));
+            }
+        }
+
+        diff.preprocess();
+        diff
+    }
+}
+
+fn restore_object(object: &Object, keys: f32) -> HitObject {
+    let time = object.time as i32;
+    let new_combo = object.new_combo;
+    let color_skip = object.colour_skip as i32;
+    let hitsound = object.hitsound;
+    let extras = HitObjectExtras {
+        sample_set: object.sample_set,
+        addition_set: object.addition_set,
+        ..Default::default()
+    };
+
+    match &object.kind {
+        ObjectKind::Circle { position } => HitObject::HitCircle(HitCircle {
+            x: position.0 as i32,
+            y: position.1 as i32,
+            new_combo,
+            color_skip,
+            time,
+            hitsound,
+            extras: extras.clone(),
+        }),
+        ObjectKind::Slider {
+            position,
+            curve,
+            slides,
+            length,
+        } => {
+            let (slider_type, curve_points) = restore_curve(curve);
+            HitObject::Slider(Slider {
+                x: position.0 as i32,
+                y: position.1 as i32,
+                new_combo,
+                color_skip,
+                time,
+                slider_type,
+                curve_points,
+                repeat: *slides as i32,
+                pixel_length: *length,
+                edge_hitsounds: Vec::new(),
+                edge_additions: Vec::new(),
+                hitsound,
+                extras: extras.clone(),
+            })
+        }
+        ObjectKind::Spinner { end_time } => HitObject::Spinner(Spinner {
+            x: 256,
+            y: 192,
+            new_combo,
+            color_skip,
+            time,
+            hitsound,
+            end_time: *end_time as i32,
+            extras: extras.clone(),
+        }),
+        ObjectKind::Hold { column, end_time } => HitObject::HoldNote(HoldNote {
+            x: column_x(*column, keys),
+            y: 192,
+            new_combo,
+            color_skip,
+            time,
+            hitsound,
+            end_time: *end_time as i32,
+            extras,
+        }),
+    }
+}
+
+/// A doubled point ends a bezier segment, so linear parts double every point.
+fn restore_curve(curve: &[CurveSegment]) -> (SliderType, Vec<(i32, i32)>) {
+    let to_osu = |points: &[(f32, f32)]| -> Vec<(i32, i32)> {
+        points.iter().map(|(x, y)| (*x as i32, *y as i32)).collect()
+    };
+
+    if let [segment] = curve {
+        let slider_type = match segment.kind {
+            CurveKind::Linear => SliderType::Linear,
+            CurveKind::Bezier => SliderType::Bezier,
+            CurveKind::Perfect => SliderType::Perfect,
+            CurveKind::Catmull => SliderType::Catmull,
+        };
+        return (slider_type, to_osu(&segment.points));
+    }
+
+    let mut points = Vec::new();
+    for segment in curve {
+        for point in to_osu(&segment.points) {
+            points.push(point);
+            if segment.kind == CurveKind::Linear {
+                points.push(point);
+            }
+        }
+
+        if segment.kind != CurveKind::Linear
+            && let Some(&last) = points.last()
+        {
+            points.push(last);
+        }
+    }
+    if points.len() >= 2 && points[points.len() - 1] == points[points.len() - 2] {
+        points.pop();
+    }
+
+    (SliderType::Bezier, points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::beatmap::formats::osu::OsuParser;
+
+    fn breaks(diff: &Difficulty) -> Vec<&Event> {
+        diff.events
+            .iter()
+            .filter(|e| matches!(e, Event::Break { .. }))
+            .collect()
+    }
+
+    #[test]
+    fn clover_round_trips() {
+        let original = OsuParser::from_osz("maps/clover.osz", false).unwrap();
+
+        for diff in &original.difficulties {
+            let map = ZokuMap::from(diff);
+            let reparsed = ZokuParser::parse(&ZokuWriter::to_zoku(&map)).unwrap();
+            assert_eq!(map, reparsed);
+
+            let restored = Difficulty::from(&reparsed);
+            assert_eq!(diff.general.audio_lead_in, restored.general.audio_lead_in);
+            assert_eq!(diff.general.countdown, restored.general.countdown);
+            assert_eq!(
+                diff.general.countdown_offset,
+                restored.general.countdown_offset
+            );
+            assert_eq!(diff.general.stack_leniency, restored.general.stack_leniency);
+            assert_eq!(diff.general.sample_set, restored.general.sample_set);
+            assert_eq!(breaks(diff), breaks(&restored));
+        }
+    }
+
+    #[test]
+    fn holds_and_breaks_round_trip() {
+        let text = "format = 1\n\n[metadata]\n\n[difficulty]\ncircle_size = 4.0\n\n\
+                    [[audio]]\nfile = \"audio.mp3\"\n\n[[timing]]\ntime = 0.0\n\
+                    beat_length = 500.0\n\n[[breaks]]\nstart = 2000.0\nend = 6000.0\n\n[objects]\n1000 hold 3 1500 sampleset=drum\n";
+        let map = ZokuParser::parse(text).unwrap();
+        assert_eq!(map, ZokuParser::parse(&ZokuWriter::to_zoku(&map)).unwrap());
+
+        let diff = Difficulty::from(&map);
+        assert_eq!(ZokuMap::from(&diff).objects, map.objects);
+        assert_eq!(ZokuMap::from(&diff).breaks, map.breaks);
+        assert_eq!(diff.general.countdown, Countdown::None);
+    }
+}
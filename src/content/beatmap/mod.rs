@@ -1,23 +1,319 @@
+use std::sync::Arc;
+
+use assets::Assets;
+use bitflags::bitflags;
+use formats::osu::Error;
+
+pub mod assets;
 pub mod formats;
 
 pub struct Beatmap {
     pub difficulties: Vec<Difficulty>,
+    /// Problems skipped over while loading in lenient mode.
+    pub warnings: Vec<Error>,
 }
 
 #[derive(Default)]
 pub struct Difficulty {
     pub version: i32,
-    pub audio_bytes: Vec<u8>,
+    pub assets: Option<Arc<Assets>>,
     pub general: GeneralSection,
     pub editor: EditorSection,
     pub metadata: MetadataSection,
     pub timing_points: Vec<TimingPoint>,
     pub hit_objects: Vec<HitObject>,
     pub difficulty: DifficultySection,
+    pub events: Vec<Event>,
     pub colours: ColoursSection,
+    /// One entry per hit object, filled in by `Difficulty::preprocess`.
+    pub object_info: Vec<ObjectInfo>,
 }
 
-#[derive(Debug)]
+impl Difficulty {
+    /// The song, shared with every other difficulty of the set.
+    pub fn audio(&self) -> std::io::Result<Arc<[u8]>> {
+        self.asset(&self.general.audio_filename)
+    }
+
+    pub fn asset(&self, name: &str) -> std::io::Result<Arc<[u8]>> {
+        self.assets
+            .as_ref()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no assets loaded"))?
+            .get(name)
+    }
+
+    /// The timing point in effect at `time`, or the first one if `time` is before all of them.
+    pub fn timing_point_at(&self, time: f32) -> Option<&TimingPoint> {
+        let next = self.timing_points.partition_point(|p| p.offset <= time);
+        self.timing_points.get(next.saturating_sub(1))
+    }
+
+    /// Samples of a hit at `time`.
+    #[allow(dead_code)] // Until hitsounds are played
+    pub fn samples(&self, time: i32, extras: &HitObjectExtras) -> Samples {
+        self.resolve_samples(time, extras.sample_set, extras.addition_set, extras)
+    }
+
+    /// Samples of a slider's head, repeats or tail, which can override the slider's sample sets.
+    #[allow(dead_code)] // Until hitsounds are played
+    pub fn edge_samples(&self, slider: &Slider, edge: usize, time: i32) -> Samples {
+        let (normal, addition) = slider.edge_additions.get(edge).copied().unwrap_or_default();
+
+        self.resolve_samples(
+            time,
+            normal.or(slider.extras.sample_set),
+            addition.or(slider.extras.addition_set),
+            &slider.extras,
+        )
+    }
+
+    /// Beat length of the uninherited point and the slider velocity multiplier in effect at `time`.
+    pub fn beat_at(&self, time: f32) -> (f32, f32) {
+        let next = self.timing_points.partition_point(|p| p.offset <= time);
+        // Only the latest inherited point after the uninherited one counts
+        let mut velocity = None;
+
+        for point in self.timing_points[..next].iter().rev() {
+            if point.ms_per_beat > 0.0 {
+                return (point.ms_per_beat, velocity.unwrap_or(1.0));
+            }
+            velocity.get_or_insert((-100.0 / point.ms_per_beat).clamp(0.1, 10.0));
+        }
+
+        let beat_length = self
+            .timing_points
+            .iter()
+            .find(|p| p.ms_per_beat > 0.0)
+            .map_or(500.0, |p| p.ms_per_beat);
+        (beat_length, velocity.unwrap_or(1.0))
+    }
+
+    /// Time it takes to slide across the whole slider once.
+    pub fn span_duration(&self, slider: &Slider) -> f32 {
+        let (beat_length, velocity) = self.beat_at(slider.time as f32);
+        slider.pixel_length / (100.0 * self.difficulty.slider_multiplier * velocity) * beat_length
+    }
+
+    /// Slider timing from the timing point in effect at the slider's start. Changes in the
+    /// middle of a slider don't affect it.
+    pub fn slider_timing(&self, slider: &Slider) -> SliderTiming {
+        let span_duration = self.span_duration(slider);
+        let duration = span_duration * slider.repeat as f32;
+        let (beat_length, _) = self.beat_at(slider.time as f32);
+        let tick_interval = beat_length / self.difficulty.slider_tick_rate;
+
+        // Ticks sit at fixed distances along the path, so they are mirrored on reverse slides.
+        let mut offsets = Vec::new();
+        if tick_interval > 0.0 {
+            let mut offset = tick_interval;
+            while offset < span_duration - TICK_END_GAP {
+                offsets.push(offset);
+                offset += tick_interval;
+            }
+        }
+
+        let mut ticks = Vec::with_capacity(offsets.len() * slider.repeat.max(0) as usize);
+        for span in 0..slider.repeat {
+            let start = slider.time as f32 + span_duration * span as f32;
+            if span % 2 == 0 {
+                ticks.extend(offsets.iter().map(|o| start + o));
+            } else {
+                ticks.extend(offsets.iter().rev().map(|o| start + span_duration - o));
+            }
+        }
+
+        SliderTiming {
+            span_duration,
+            duration,
+            end_time: slider.time as f32 + duration,
+            ticks,
+        }
+    }
+
+    /// Computes `object_info`. Must be called again whenever hit objects or timing points change.
+    pub fn preprocess(&mut self) {
+        let mut info: Vec<ObjectInfo> = Vec::with_capacity(self.hit_objects.len());
+
+        for (i, object) in self.hit_objects.iter().enumerate() {
+            let previous = i.checked_sub(1).map(|p| (&self.hit_objects[p], &info[p]));
+            let new_combo = match previous {
+                None => true,
+                Some((HitObject::Spinner(_), _)) => true,
+                Some(_) => object.new_combo(),
+            };
+
+            let (combo, combo_number, colour_index) = match previous {
+                None => (0, 1, 0),
+                Some((_, last)) if !new_combo => {
+                    (last.combo, last.combo_number + 1, last.colour_index)
+                }
+                Some((_, last)) => (
+                    last.combo + 1,
+                    1,
+                    last.colour_index + 1 + object.color_skip().max(0) as usize,
+                ),
+            };
+
+            let slider = match object {
+                HitObject::Slider(slider) => Some(self.slider_timing(slider)),
+                _ => None,
+            };
+
+            info.push(ObjectInfo {
+                combo,
+                combo_number,
+                colour_index,
+                stack_height: 0,
+                end_time: slider
+                    .as_ref()
+                    .map_or_else(|| object.end_time(self), |s| s.end_time),
+                slider,
+                last_in_combo: true,
+            });
+            if let Some(last) = i.checked_sub(1) {
+                info[last].last_in_combo = new_combo;
+            }
+        }
+
+        self.object_info = info;
+        if self.general.game_mode == GameMode::Osu {
+            self.compute_stacking();
+        }
+    }
+
+    /// Offsets objects that would otherwise be drawn on top of each other, using the official
+    /// client's v6 algorithm for maps of every version.
+    fn compute_stacking(&mut self) {
+        const STACK_DISTANCE: f32 = 3.0;
+
+        let threshold = self.difficulty.preempt() * self.general.stack_leniency;
+        let objects = &self.hit_objects;
+        let info = &mut self.object_info;
+        let near = |a: (i32, i32), b: (i32, i32)| {
+            ((a.0 - b.0) as f32).hypot((a.1 - b.1) as f32) < STACK_DISTANCE
+        };
+
+        for i in (0..objects.len()).rev() {
+            if info[i].stack_height != 0 || matches!(objects[i], HitObject::Spinner(_)) {
+                continue;
+            }
+
+            let mut top = i;
+            for n in (0..i).rev() {
+                if matches!(objects[n], HitObject::Spinner(_)) {
+                    continue;
+                }
+
+                let start = objects[top].time() as f32;
+                if let HitObject::Slider(_) = objects[i] {
+                    if start - objects[n].time() as f32 > threshold {
+                        break;
+                    }
+                    if near(objects[n].end_position(), objects[top].position()) {
+                        info[n].stack_height = info[top].stack_height + 1;
+                        top = n;
+                    }
+                    continue;
+                }
+
+                if start - info[n].end_time > threshold {
+                    break;
+                }
+
+                // Circles stacked on a slider's end move away from it instead.
+                if let HitObject::Slider(_) = objects[n]
+                    && near(objects[n].end_position(), objects[top].position())
+                {
+                    let offset = info[top].stack_height - info[n].stack_height + 1;
+                    for j in n + 1..=i {
+                        if near(objects[n].end_position(), objects[j].position()) {
+                            info[j].stack_height -= offset;
+                        }
+                    }
+                    break;
+                }
+
+                if near(objects[n].position(), objects[top].position()) {
+                    info[n].stack_height = info[top].stack_height + 1;
+                    top = n;
+                }
+            }
+        }
+    }
+
+    /// `Auto` and zero fields fall back to the active timing point, then to the map's default.
+    fn resolve_samples(
+        &self,
+        time: i32,
+        normal: SampleSet,
+        addition: SampleSet,
+        extras: &HitObjectExtras,
+    ) -> Samples {
+        let point = self.timing_point_at(time as f32);
+        let normal = normal
+            .or(point.map_or(SampleSet::Auto, |p| p.sample_set))
+            .or(self.general.sample_set);
+
+        Samples {
+            normal,
+            addition: addition.or(normal),
+            index: match extras.custom_index {
+                0 => point.map_or(0, |p| p.sample_index),
+                index => index,
+            },
+            volume: match extras.sample_volume {
+                0 => point.map_or(100, |p| p.volume),
+                volume => volume,
+            },
+        }
+    }
+}
+
+bitflags! {
+    /// Sounds added to a hit. The official client plays the hit normal whether or not
+    /// `NORMAL` is set.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct HitSound: u8 {
+        const NORMAL = 1;
+        const WHISTLE = 2;
+        const FINISH = 4;
+        const CLAP = 8;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleSet {
+    /// Inherit from the timing point or the map.
+    #[default]
+    Auto,
+    Normal,
+    Soft,
+    Drum,
+}
+
+impl SampleSet {
+    /// `self`, unless it is `Auto`.
+    pub fn or(self, fallback: SampleSet) -> SampleSet {
+        match self {
+            SampleSet::Auto => fallback,
+            set => set,
+        }
+    }
+}
+
+/// Which samples to play for a hit, with nothing left to inherit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Samples {
+    /// Set of the hit normal.
+    pub normal: SampleSet,
+    /// Set of the whistle, finish and clap.
+    pub addition: SampleSet,
+    /// Custom sample index, 0 for the skin's samples.
+    pub index: i32,
+    pub volume: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GameMode {
     Osu,
     Taiko,
@@ -25,13 +321,31 @@ pub enum GameMode {
     Mania,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Countdown {
+    None,
+    Normal,
+    Half,
+    Double,
+}
+
+/// Whether hit circle numbers are drawn above or below the hit circle overlay.
+#[derive(Debug, PartialEq)]
+pub enum OverlayPosition {
+    NoChange,
+    Below,
+    Above,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct GeneralSection {
     pub audio_filename: String,
     pub audio_lead_in: i32,
+    pub audio_hash: String,
     pub preview_time: i32,
-    pub countdown: bool,
-    pub sample_set: String,
+    pub countdown: Countdown,
+    pub countdown_offset: i32,
+    pub sample_set: SampleSet,
     pub stack_leniency: f32,
     pub game_mode: GameMode,
     pub letterbox_in_breaks: bool,
@@ -40,6 +354,12 @@ pub struct GeneralSection {
     pub special_style: bool,
     pub epilepsy_warning: bool,
     pub use_skin_sprites: bool,
+    pub always_show_playfield: bool,
+    pub overlay_position: OverlayPosition,
+    pub skin_preference: String,
+    pub samples_match_playback_rate: bool,
+    /// Keys this parser doesn't know, kept in file order so they survive a rewrite.
+    pub unknown: Vec<(String, String)>,
 }
 
 impl Default for GeneralSection {
@@ -47,10 +367,12 @@ impl Default for GeneralSection {
         GeneralSection {
             audio_filename: String::new(),
             audio_lead_in: 0,
+            audio_hash: String::new(),
             preview_time: 0,
-            countdown: false,
-            sample_set: String::new(),
-            stack_leniency: 0.0,
+            countdown: Countdown::Normal,
+            countdown_offset: 0,
+            sample_set: SampleSet::Normal,
+            stack_leniency: 0.7,
             game_mode: GameMode::Osu,
             letterbox_in_breaks: false,
             widescreen_storyboard: false,
@@ -58,16 +380,23 @@ impl Default for GeneralSection {
             special_style: false,
             epilepsy_warning: false,
             use_skin_sprites: false,
+            always_show_playfield: false,
+            overlay_position: OverlayPosition::NoChange,
+            skin_preference: String::new(),
+            samples_match_playback_rate: false,
+            unknown: Vec::new(),
         }
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct EditorSection {
     pub bookmarks: Vec<i32>,
     pub distance_spacing: f32,
     pub beat_divisor: i32,
     pub grid_size: i32,
     pub timeline_zoom: f32,
+    pub unknown: Vec<(String, String)>,
 }
 
 impl Default for EditorSection {
@@ -78,10 +407,12 @@ impl Default for EditorSection {
             beat_divisor: 4,
             grid_size: 4,
             timeline_zoom: 1.0,
+            unknown: Vec::new(),
         }
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct MetadataSection {
     pub title: String,
     pub title_unicode: String,
@@ -93,6 +424,7 @@ pub struct MetadataSection {
     pub tags: Vec<String>,
     pub beatmap_id: i32,
     pub beatmap_set_id: i32,
+    pub unknown: Vec<(String, String)>,
 }
 
 impl Default for MetadataSection {
@@ -108,11 +440,12 @@ impl Default for MetadataSection {
             tags: Vec::new(),
             beatmap_id: 0,
             beatmap_set_id: 0,
+            unknown: Vec::new(),
         }
     }
 }
 
-#[derive(Default)]
+#[derive(Debug, PartialEq)]
 pub struct DifficultySection {
     pub hp_drain_rate: f32,
     pub circle_size: f32,
@@ -120,21 +453,68 @@ pub struct DifficultySection {
     pub approach_rate: f32,
     pub slider_multiplier: f32,
     pub slider_tick_rate: f32,
+    pub unknown: Vec<(String, String)>,
+}
+
+impl DifficultySection {
+    /// How long before its time an object appears, from the approach rate.
+    pub fn preempt(&self) -> f32 {
+        if self.approach_rate < 5. {
+            1200. + 600. * (5. - self.approach_rate) / 5.
+        } else {
+            1200. - 750. * (self.approach_rate - 5.) / 5.
+        }
+    }
 }
 
-#[derive(Clone)]
+impl Default for DifficultySection {
+    fn default() -> Self {
+        DifficultySection {
+            hp_drain_rate: 5.0,
+            circle_size: 5.0,
+            overall_difficulty: 5.0,
+            approach_rate: 5.0,
+            slider_multiplier: 1.4,
+            slider_tick_rate: 1.0,
+            unknown: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Background {
+        filename: String,
+        x: i32,
+        y: i32,
+    },
+    Video {
+        start_time: i32,
+        filename: String,
+        x: i32,
+        y: i32,
+    },
+    Break {
+        start_time: i32,
+        end_time: i32,
+    },
+    /// Comments and storyboard commands, kept verbatim.
+    Other(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct TimingPoint {
     pub offset: f32,
     pub ms_per_beat: f32,
     pub meter: i32,
-    pub sample_set: String,
+    pub sample_set: SampleSet,
     pub sample_index: i32,
     pub volume: i32,
     pub inherited: bool,
     pub kiai_mode: bool,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum HitObject {
     HitCircle(HitCircle),
     Slider(Slider),
@@ -142,18 +522,123 @@ pub enum HitObject {
     HoldNote(HoldNote),
 }
 
-#[derive(Clone)]
+impl HitObject {
+    pub fn position(&self) -> (i32, i32) {
+        match self {
+            HitObject::HitCircle(o) => (o.x, o.y),
+            HitObject::Slider(o) => (o.x, o.y),
+            HitObject::Spinner(o) => (o.x, o.y),
+            HitObject::HoldNote(o) => (o.x, o.y),
+        }
+    }
+
+    /// Where the object is when it ends. For sliders with an odd number of slides this is
+    /// approximated by the last control point.
+    pub fn end_position(&self) -> (i32, i32) {
+        match self {
+            HitObject::Slider(o) if o.repeat % 2 == 1 => {
+                o.curve_points.last().copied().unwrap_or((o.x, o.y))
+            }
+            _ => self.position(),
+        }
+    }
+
+    pub fn time(&self) -> i32 {
+        match self {
+            HitObject::HitCircle(o) => o.time,
+            HitObject::Slider(o) => o.time,
+            HitObject::Spinner(o) => o.time,
+            HitObject::HoldNote(o) => o.time,
+        }
+    }
+
+    /// Slider end times depend on the timing points of `difficulty`.
+    pub fn end_time(&self, difficulty: &Difficulty) -> f32 {
+        match self {
+            HitObject::HitCircle(o) => o.time as f32,
+            HitObject::Slider(o) => o.time as f32 + difficulty.span_duration(o) * o.repeat as f32,
+            HitObject::Spinner(o) => o.end_time as f32,
+            HitObject::HoldNote(o) => o.end_time as f32,
+        }
+    }
+
+    pub fn new_combo(&self) -> bool {
+        match self {
+            HitObject::HitCircle(o) => o.new_combo,
+            HitObject::Slider(o) => o.new_combo,
+            HitObject::Spinner(o) => o.new_combo,
+            HitObject::HoldNote(o) => o.new_combo,
+        }
+    }
+
+    /// How many combo colours to skip when this object starts a new combo.
+    pub fn color_skip(&self) -> i32 {
+        match self {
+            HitObject::HitCircle(o) => o.color_skip,
+            HitObject::Slider(o) => o.color_skip,
+            HitObject::Spinner(o) => o.color_skip,
+            HitObject::HoldNote(o) => o.color_skip,
+        }
+    }
+
+    pub fn hitsound(&self) -> HitSound {
+        match self {
+            HitObject::HitCircle(o) => o.hitsound,
+            HitObject::Slider(o) => o.hitsound,
+            HitObject::Spinner(o) => o.hitsound,
+            HitObject::HoldNote(o) => o.hitsound,
+        }
+    }
+
+    pub fn extras(&self) -> &HitObjectExtras {
+        match self {
+            HitObject::HitCircle(o) => &o.extras,
+            HitObject::Slider(o) => &o.extras,
+            HitObject::Spinner(o) => &o.extras,
+            HitObject::HoldNote(o) => &o.extras,
+        }
+    }
+}
+
+/// Ticks closer than this to the end of a slide are dropped.
+const TICK_END_GAP: f32 = 10.0;
+
+/// See `Difficulty::slider_timing`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SliderTiming {
+    pub span_duration: f32,
+    pub duration: f32,
+    pub end_time: f32,
+    pub ticks: Vec<f32>,
+}
+
+/// What a hit object looks like in context, derived from the objects around it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjectInfo {
+    /// Which combo the object is in, counting from zero.
+    pub combo: usize,
+    /// The number drawn on the object, counting from one.
+    pub combo_number: usize,
+    /// Index into the combo colours, not yet wrapped around their count.
+    pub colour_index: usize,
+    pub stack_height: i32,
+    pub end_time: f32,
+    pub slider: Option<SliderTiming>,
+    pub last_in_combo: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct HitCircle {
     pub x: i32,
     pub y: i32,
     pub new_combo: bool,
     pub color_skip: i32,
     pub time: i32,
-    pub hitsound: i32,
+    pub hitsound: HitSound,
     pub extras: HitObjectExtras,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum SliderType {
     Linear,
     Bezier,
@@ -161,7 +646,7 @@ pub enum SliderType {
     Catmull,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Slider {
     pub x: i32,
     pub y: i32,
@@ -172,40 +657,41 @@ pub struct Slider {
     pub curve_points: Vec<(i32, i32)>,
     pub repeat: i32,
     pub pixel_length: f32,
-    pub edge_hitsounds: Vec<i32>,
-    pub edge_additions: Vec<(i32, i32)>,
-    pub hitsound: i32,
+    pub edge_hitsounds: Vec<HitSound>,
+    /// Normal and addition sample sets of each edge.
+    pub edge_additions: Vec<(SampleSet, SampleSet)>,
+    pub hitsound: HitSound,
     pub extras: HitObjectExtras,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Spinner {
     pub x: i32,
     pub y: i32,
     pub new_combo: bool,
     pub color_skip: i32,
     pub time: i32,
-    pub hitsound: i32,
+    pub hitsound: HitSound,
     pub end_time: i32,
     pub extras: HitObjectExtras,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct HoldNote {
     pub x: i32,
     pub y: i32,
     pub new_combo: bool,
     pub color_skip: i32,
     pub time: i32,
-    pub hitsound: i32,
+    pub hitsound: HitSound,
     pub end_time: i32,
     pub extras: HitObjectExtras,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct HitObjectExtras {
-    pub sample_set: i32,
-    pub addition_set: i32,
+    pub sample_set: SampleSet,
+    pub addition_set: SampleSet,
     pub custom_index: i32,
     pub sample_volume: i32,
     pub filename: String,
@@ -214,8 +700,8 @@ pub struct HitObjectExtras {
 impl Default for HitObjectExtras {
     fn default() -> Self {
         HitObjectExtras {
-            sample_set: 0,
-            addition_set: 0,
+            sample_set: SampleSet::Auto,
+            addition_set: SampleSet::Auto,
             custom_index: 0,
             sample_volume: 0,
             filename: String::new(),
@@ -223,15 +709,16 @@ impl Default for HitObjectExtras {
     }
 }
 
-#[derive(Default, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Default, PartialEq, PartialOrd, Eq, Ord)]
 pub struct Colour(i32, i32, i32);
 
-#[derive(Default)]
+#[derive(Debug, Default, PartialEq)]
 pub struct ColoursSection {
     pub colours: Vec<Colour>,
     pub slider_body: Colour,
     pub slider_track_override: Colour,
     pub slider_border: Colour,
+    pub unknown: Vec<(String, String)>,
 }
 
 enum Section {
@@ -242,6 +729,116 @@ enum Section {
     HitObjects(Vec<HitObject>),
     Difficulty(DifficultySection),
     Colours(ColoursSection),
-    Events,
+    Events(Vec<Event>),
+    Skipped,
     None,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(offset: f32, sample_set: SampleSet, sample_index: i32) -> TimingPoint {
+        TimingPoint {
+            offset,
+            ms_per_beat: 500.,
+            meter: 4,
+            sample_set,
+            sample_index,
+            volume: 60,
+            inherited: true,
+            kiai_mode: false,
+        }
+    }
+
+    #[test]
+    fn velocity_comes_from_the_latest_inherited_point() {
+        let green = |offset, ms_per_beat| TimingPoint {
+            ms_per_beat,
+            inherited: false,
+            ..point(offset, SampleSet::Auto, 0)
+        };
+        let diff = Difficulty {
+            timing_points: vec![
+                point(0., SampleSet::Auto, 0),
+                green(1000., -50.),
+                green(2000., -100.),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(diff.beat_at(500.), (500., 1.));
+        assert_eq!(diff.beat_at(1500.), (500., 2.));
+        // Going back to 1x must not pick up the 2x before it
+        assert_eq!(diff.beat_at(2500.), (500., 1.));
+    }
+
+    #[test]
+    fn samples_inherit_from_timing_points_then_the_map() {
+        let mut diff = Difficulty::default();
+        diff.general.sample_set = SampleSet::Normal;
+        diff.timing_points = vec![
+            point(0., SampleSet::Soft, 2),
+            point(1000., SampleSet::Auto, 0),
+        ];
+
+        let auto = HitObjectExtras::default();
+        let samples = diff.samples(500, &auto);
+        assert_eq!(samples.normal, SampleSet::Soft);
+        assert_eq!(samples.addition, SampleSet::Soft);
+        assert_eq!(samples.index, 2);
+        assert_eq!(samples.volume, 60);
+
+        // Before the first point its settings still apply
+        assert_eq!(diff.samples(-100, &auto).normal, SampleSet::Soft);
+        assert_eq!(diff.samples(1500, &auto).normal, SampleSet::Normal);
+
+        let drum = HitObjectExtras {
+            sample_set: SampleSet::Drum,
+            custom_index: 3,
+            sample_volume: 80,
+            ..Default::default()
+        };
+        let samples = diff.samples(500, &drum);
+        assert_eq!(samples.normal, SampleSet::Drum);
+        assert_eq!(samples.addition, SampleSet::Drum);
+        assert_eq!(samples.index, 3);
+        assert_eq!(samples.volume, 80);
+    }
+
+    #[test]
+    fn slider_edges_override_the_slider() {
+        let diff = Difficulty {
+            timing_points: vec![point(0., SampleSet::Normal, 0)],
+            ..Default::default()
+        };
+
+        let slider = Slider {
+            x: 0,
+            y: 0,
+            new_combo: false,
+            color_skip: 0,
+            time: 0,
+            slider_type: SliderType::Linear,
+            curve_points: vec![(100, 0)],
+            repeat: 1,
+            pixel_length: 100.,
+            edge_hitsounds: Vec::new(),
+            edge_additions: vec![(SampleSet::Auto, SampleSet::Soft)],
+            hitsound: HitSound::empty(),
+            extras: HitObjectExtras {
+                sample_set: SampleSet::Drum,
+                ..Default::default()
+            },
+        };
+
+        let head = diff.edge_samples(&slider, 0, 0);
+        assert_eq!(head.normal, SampleSet::Drum);
+        assert_eq!(head.addition, SampleSet::Soft);
+
+        // Edges without their own sets use the slider's
+        let tail = diff.edge_samples(&slider, 1, 500);
+        assert_eq!(tail.normal, SampleSet::Drum);
+        assert_eq!(tail.addition, SampleSet::Drum);
+    }
+}
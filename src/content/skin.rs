@@ -1,5 +1,6 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
+use kira::sound::static_sound::StaticSoundData;
 use macroquad::prelude::*;
 
 #[derive(Clone)]
@@ -9,8 +10,110 @@ pub struct Skin {
     pub hit_circle_overlay: Texture2D,
     pub slider_start_circle: Texture2D,
     pub slider_start_circle_overlay: Texture2D,
+    pub follow_points: Vec<Texture2D>,
+    pub cursor: Texture2D,
+    pub cursor_trail: Texture2D,
+    pub cursor_middle: Option<Texture2D>,
+    pub cursor_smoke: Texture2D,
+    pub input_overlay_background: Texture2D,
+    pub input_overlay_key: Texture2D,
+    pub play_skip: Texture2D,
+    pub ready: Texture2D,
+    pub count3: Texture2D,
+    pub count2: Texture2D,
+    pub count1: Texture2D,
+    pub go: Texture2D,
+    pub section_pass: Texture2D,
+    pub section_fail: Texture2D,
+    pub arrow_warning: Texture2D,
+    pub star2: Texture2D,
+
+    pub hit300: Texture2D,
+    pub hit300g: Texture2D,
+    pub hit100: Texture2D,
+    pub hit100k: Texture2D,
+    pub hit50: Texture2D,
+    pub hit0: Texture2D,
+    pub ranking_panel: Texture2D,
+    pub ranking_graph: Texture2D,
+    pub ranking_maxcombo: Texture2D,
+    pub ranking_accuracy: Texture2D,
+    /// `ranking-<grade>.png` by grade name, e.g. "XH".
+    pub ranking_grades: HashMap<&'static str, Texture2D>,
+    /// `scoreentry-*.png` by the character they draw.
+    pub score_entry: HashMap<char, Texture2D>,
+    /// `selection-mod-<name>.png` by mod name, for the mods the skin has icons for.
+    pub mod_icons: HashMap<&'static str, Texture2D>,
+
+    pub ready_sound: Option<StaticSoundData>,
+    pub count3_sound: Option<StaticSoundData>,
+    pub count2_sound: Option<StaticSoundData>,
+    pub count1_sound: Option<StaticSoundData>,
+    pub go_sound: Option<StaticSoundData>,
+    pub section_pass_sound: Option<StaticSoundData>,
+    pub section_fail_sound: Option<StaticSoundData>,
 
     pub font: Font,
+    pub config: SkinConfig,
+}
+
+/// Values read from `skin.ini`.
+#[derive(Clone, Debug)]
+pub struct SkinConfig {
+    pub cursor_rotate: bool,
+    pub cursor_expand: bool,
+    pub cursor_centre: bool,
+    pub input_overlay_text: Color,
+}
+
+impl Default for SkinConfig {
+    fn default() -> Self {
+        SkinConfig {
+            cursor_rotate: true,
+            cursor_expand: true,
+            cursor_centre: true,
+            input_overlay_text: BLACK,
+        }
+    }
+}
+
+impl SkinConfig {
+    /// skin.ini files are hand edited and often contain junk, so anything
+    /// that does not look like `Key: Value` is ignored.
+    pub fn parse(input: &str) -> Self {
+        let mut config = Self::default();
+        let mut section = "";
+
+        for line in input.lines().map(|l| l.trim()) {
+            if line.starts_with("//") {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') && !line.contains(',') {
+                section = &line[1..line.len() - 1];
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match (section, key.trim()) {
+                ("General", "CursorRotate") => config.cursor_rotate = value != "0",
+                ("General", "CursorExpand") => config.cursor_expand = value != "0",
+                ("General", "CursorCentre") => config.cursor_centre = value != "0",
+                ("Colours", "InputOverlayText") => {
+                    if let Some(colour) = parse_colour(value) {
+                        config.input_overlay_text = colour;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        config
+    }
 }
 
 impl Skin {
@@ -21,6 +124,9 @@ impl Skin {
         let slider_start_circle_path = path.join("sliderstartcircle.png");
         let slider_start_circle_overlay_path = path.join("sliderstartcircleoverlay.png");
         let font_path = path.join("font.ttf");
+        let config = std::fs::read(path.join("skin.ini"))
+            .map(|bytes| SkinConfig::parse(&String::from_utf8_lossy(&bytes)))
+            .unwrap_or_default();
 
         Self {
             font: load_ttf_font(font_path.to_str().unwrap()).await.unwrap(),
@@ -29,6 +135,146 @@ impl Skin {
             hit_circle_overlay: load_texture(hit_circle_overlay_path.to_str().unwrap()).await.unwrap(),
             slider_start_circle: load_texture(slider_start_circle_path.to_str().unwrap()).await.unwrap(),
             slider_start_circle_overlay: load_texture(slider_start_circle_overlay_path.to_str().unwrap()).await.unwrap(),
+            follow_points: Self::load_animation(path, "followpoint").await,
+            cursor: Self::load_image(path, "cursor").await,
+            cursor_trail: Self::load_image(path, "cursortrail").await,
+            cursor_middle: Self::load_optional_image(path, "cursormiddle").await,
+            cursor_smoke: Self::load_image(path, "cursor-smoke").await,
+            input_overlay_background: Self::load_image(path, "inputoverlay-background").await,
+            input_overlay_key: Self::load_image(path, "inputoverlay-key").await,
+            play_skip: Self::load_image(path, "play-skip").await,
+            ready: Self::load_image(path, "ready").await,
+            count3: Self::load_image(path, "count3").await,
+            count2: Self::load_image(path, "count2").await,
+            count1: Self::load_image(path, "count1").await,
+            go: Self::load_image(path, "go").await,
+            section_pass: Self::load_image(path, "section-pass").await,
+            section_fail: Self::load_image(path, "section-fail").await,
+            arrow_warning: Self::load_image(path, "arrow-warning").await,
+            star2: Self::load_image(path, "star2").await,
+            hit300: Self::load_image(path, "hit300").await,
+            hit300g: Self::load_image(path, "hit300g").await,
+            hit100: Self::load_image(path, "hit100").await,
+            hit100k: Self::load_image(path, "hit100k").await,
+            hit50: Self::load_image(path, "hit50").await,
+            hit0: Self::load_image(path, "hit0").await,
+            ranking_panel: Self::load_image(path, "ranking-panel").await,
+            ranking_graph: Self::load_image(path, "ranking-graph").await,
+            ranking_maxcombo: Self::load_image(path, "ranking-maxcombo").await,
+            ranking_accuracy: Self::load_image(path, "ranking-accuracy").await,
+            ranking_grades: Self::load_ranking_grades(path).await,
+            score_entry: Self::load_score_entry(path).await,
+            mod_icons: Self::load_mod_icons(path).await,
+            ready_sound: Self::load_sound(path, "readys"),
+            count3_sound: Self::load_sound(path, "count3s"),
+            count2_sound: Self::load_sound(path, "count2s"),
+            count1_sound: Self::load_sound(path, "count1s"),
+            go_sound: Self::load_sound(path, "gos"),
+            section_pass_sound: Self::load_sound(path, "sectionpass"),
+            section_fail_sound: Self::load_sound(path, "sectionfail"),
+            config,
         }
     }
-}
\ No newline at end of file
+
+    async fn load_image(path: &Path, name: &str) -> Texture2D {
+        let image_path = path.join(format!("{}.png", name));
+        load_texture(image_path.to_str().unwrap()).await.unwrap()
+    }
+
+    async fn load_optional_image(path: &Path, name: &str) -> Option<Texture2D> {
+        let image_path = path.join(format!("{}.png", name));
+        if image_path.exists() {
+            load_texture(image_path.to_str().unwrap()).await.ok()
+        } else {
+            None
+        }
+    }
+
+    async fn load_ranking_grades(path: &Path) -> HashMap<&'static str, Texture2D> {
+        let mut grades = HashMap::new();
+        for grade in ["XH", "X", "SH", "S", "A", "B", "C", "D"] {
+            let texture = Self::load_image(path, &format!("ranking-{}", grade)).await;
+            grades.insert(grade, texture);
+        }
+        grades
+    }
+
+    async fn load_score_entry(path: &Path) -> HashMap<char, Texture2D> {
+        let names = ('0'..='9').map(|c| (c, c.to_string())).chain(
+            [(',', "comma"), ('.', "dot"), ('%', "percent"), ('x', "x")]
+                .map(|(c, name)| (c, name.to_string())),
+        );
+
+        let mut glyphs = HashMap::new();
+        for (c, name) in names {
+            let texture = Self::load_image(path, &format!("scoreentry-{}", name)).await;
+            glyphs.insert(c, texture);
+        }
+        glyphs
+    }
+
+    async fn load_mod_icons(path: &Path) -> HashMap<&'static str, Texture2D> {
+        const MODS: [&str; 13] = [
+            "easy",
+            "nofail",
+            "halftime",
+            "hardrock",
+            "suddendeath",
+            "perfect",
+            "doubletime",
+            "nightcore",
+            "hidden",
+            "flashlight",
+            "relax",
+            "autoplay",
+            "spunout",
+        ];
+
+        let mut icons = HashMap::new();
+        for name in MODS {
+            let icon_name = format!("selection-mod-{}", name);
+            if let Some(texture) = Self::load_optional_image(path, &icon_name).await {
+                icons.insert(name, texture);
+            }
+        }
+        icons
+    }
+
+    /// Skins may leave out any sound, and use any of the formats the official client reads.
+    fn load_sound(path: &Path, name: &str) -> Option<StaticSoundData> {
+        ["wav", "mp3", "ogg"]
+            .iter()
+            .map(|ext| path.join(format!("{}.{}", name, ext)))
+            .find(|p| p.exists())
+            .and_then(|p| StaticSoundData::from_file(p).ok())
+    }
+
+    /// Loads the frames `name-0.png`, `name-1.png`, ... or falls back to a single `name.png`.
+    async fn load_animation(path: &Path, name: &str) -> Vec<Texture2D> {
+        let mut frames = Vec::new();
+
+        loop {
+            let frame_path = path.join(format!("{}-{}.png", name, frames.len()));
+            if !frame_path.exists() {
+                break;
+            }
+            frames.push(load_texture(frame_path.to_str().unwrap()).await.unwrap());
+        }
+
+        if frames.is_empty() {
+            frames.push(Self::load_image(path, name).await);
+        }
+
+        frames
+    }
+}
+
+fn parse_colour(s: &str) -> Option<Color> {
+    let mut iter = s.split(',').map(|c| c.trim().parse::<u8>());
+    Some(Color::from_rgba(
+        iter.next()?.ok()?,
+        iter.next()?.ok()?,
+        iter.next()?.ok()?,
+        255,
+    ))
+}
@@ -0,0 +1,155 @@
+use macroquad::prelude::*;
+
+use crate::content::{
+    beatmap::{Difficulty, Event},
+    skin::Skin,
+};
+
+use super::{judgement::ScoreProcessor, music::MusicManager};
+
+/// How long the pass or fail sprite stays on screen.
+const VERDICT_DURATION: f32 = 1500.;
+/// How long before a break ends the warning arrows appear.
+const WARNING_DURATION: f32 = 1000.;
+const LETTERBOX_FADE: f32 = 200.;
+
+struct Break {
+    start: f32,
+    end: f32,
+    /// Whether health was at least half way through the break, once it got there.
+    passed: Option<bool>,
+}
+
+impl Break {
+    fn middle(&self) -> f32 {
+        (self.start + self.end) / 2.
+    }
+}
+
+/// Feedback shown during the breaks of `[Events]`.
+pub struct BreakOverlay {
+    breaks: Vec<Break>,
+    letterbox: bool,
+    last_time: f32,
+}
+
+impl BreakOverlay {
+    pub fn new(difficulty: &Difficulty) -> Self {
+        let breaks = difficulty
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Break {
+                    start_time,
+                    end_time,
+                } => Some(Break {
+                    start: *start_time as f32,
+                    end: *end_time as f32,
+                    passed: None,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            breaks,
+            letterbox: difficulty.general.letterbox_in_breaks,
+            last_time: f32::NEG_INFINITY,
+        }
+    }
+
+    /// Judges each break half way through it.
+    pub fn update(&mut self, skin: &Skin, music: &mut MusicManager, score: &ScoreProcessor) {
+        let time = music.time;
+
+        for b in &mut self.breaks {
+            let middle = b.middle();
+            if time < middle {
+                b.passed = None;
+            } else if self.last_time < middle && b.passed.is_none() {
+                let passed = score.health >= 0.5;
+                b.passed = Some(passed);
+
+                let sound = if passed {
+                    &skin.section_pass_sound
+                } else {
+                    &skin.section_fail_sound
+                };
+                if let Some(sound) = sound {
+                    music.play_sound(sound);
+                }
+            }
+        }
+
+        self.last_time = time;
+    }
+
+    /// Forgets every verdict, so breaks jumped over stay unjudged instead of playing their
+    /// sound or showing a stale result.
+    pub fn seek(&mut self, time: f32) {
+        for b in &mut self.breaks {
+            b.passed = None;
+        }
+        self.last_time = time;
+    }
+
+    pub fn render(&self, skin: &Skin, time: f32, playfield: Rect) {
+        let Some(current) = self
+            .breaks
+            .iter()
+            .find(|b| (b.start..b.end).contains(&time))
+        else {
+            return;
+        };
+
+        if self.letterbox {
+            let alpha = ((time - current.start).min(current.end - time) / LETTERBOX_FADE).min(1.);
+            let height = screen_height() * 0.125;
+            let colour = Color::new(0., 0., 0., alpha);
+            draw_rectangle(0., 0., screen_width(), height, colour);
+            draw_rectangle(0., screen_height() - height, screen_width(), height, colour);
+        }
+
+        if let Some(passed) = current.passed
+            && time - current.middle() < VERDICT_DURATION
+        {
+            let texture = if passed {
+                &skin.section_pass
+            } else {
+                &skin.section_fail
+            };
+            let alpha = 1. - (time - current.middle()) / VERDICT_DURATION;
+            let size = texture.size() * (screen_height() / 768.);
+            draw_texture_ex(
+                texture,
+                screen_width() / 2. - size.x / 2.,
+                screen_height() / 2. - size.y / 2.,
+                Color::new(1., 1., 1., alpha),
+                DrawTextureParams {
+                    dest_size: Some(size),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Blinks a few times on both sides of the playfield, pointing inwards
+        let remaining = current.end - time;
+        if remaining < WARNING_DURATION && (remaining / 200.) as i32 % 2 == 0 {
+            let size = skin.arrow_warning.size() * (screen_height() / 768.);
+            let y = playfield.center().y - size.y / 2.;
+            for (x, flip_x) in [(playfield.x - size.x, false), (playfield.right(), true)] {
+                draw_texture_ex(
+                    &skin.arrow_warning,
+                    x,
+                    y,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(size),
+                        flip_x,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
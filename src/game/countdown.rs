@@ -0,0 +1,140 @@
+use kira::sound::static_sound::StaticSoundData;
+use macroquad::prelude::*;
+
+use crate::content::{
+    beatmap::{Countdown, Difficulty},
+    skin::Skin,
+};
+
+use super::music::MusicManager;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Ready,
+    Three,
+    Two,
+    One,
+    Go,
+}
+
+impl Step {
+    fn texture(self, skin: &Skin) -> &Texture2D {
+        match self {
+            Step::Ready => &skin.ready,
+            Step::Three => &skin.count3,
+            Step::Two => &skin.count2,
+            Step::One => &skin.count1,
+            Step::Go => &skin.go,
+        }
+    }
+
+    fn sound(self, skin: &Skin) -> Option<&StaticSoundData> {
+        match self {
+            Step::Ready => skin.ready_sound.as_ref(),
+            Step::Three => skin.count3_sound.as_ref(),
+            Step::Two => skin.count2_sound.as_ref(),
+            Step::One => skin.count1_sound.as_ref(),
+            Step::Go => skin.go_sound.as_ref(),
+        }
+    }
+}
+
+/// The "ready, 3, 2, 1, go" before the first object.
+pub struct CountdownRenderer {
+    steps: Vec<(f32, Step)>,
+    beat_length: f32,
+    last_time: f32,
+}
+
+impl CountdownRenderer {
+    pub fn new(difficulty: &Difficulty) -> Self {
+        let mut countdown = Self {
+            steps: Vec::new(),
+            beat_length: 0.,
+            last_time: f32::NEG_INFINITY,
+        };
+
+        let speed = match difficulty.general.countdown {
+            Countdown::None => return countdown,
+            Countdown::Normal => 1.,
+            Countdown::Half => 2.,
+            Countdown::Double => 0.5,
+        };
+        let (Some(first), Some(point)) = (
+            difficulty.hit_objects.first(),
+            difficulty.timing_points.iter().find(|p| p.ms_per_beat > 0.),
+        ) else {
+            return countdown;
+        };
+
+        // "Go" lands on the last beat at least one beat before the first object.
+        let beat = point.ms_per_beat * speed;
+        let beats = ((first.time() as f32 - beat - point.offset) / beat).floor();
+        let go = point.offset + (beats - difficulty.general.countdown_offset as f32) * beat;
+
+        countdown.beat_length = beat;
+        countdown.steps = vec![
+            (go - 5. * beat, Step::Ready),
+            (go - 3. * beat, Step::Three),
+            (go - 2. * beat, Step::Two),
+            (go - beat, Step::One),
+            (go, Step::Go),
+        ];
+        countdown
+    }
+
+    /// When the game clock has to start for the whole countdown to play.
+    pub fn start_time(&self) -> Option<f32> {
+        self.steps.first().map(|(time, _)| *time)
+    }
+
+    /// Jumping over a step should not play its sound, so this forgets where the clock was.
+    pub fn seek(&mut self, time: f32) {
+        self.last_time = time;
+    }
+
+    /// Plays the sound of every step reached since the last frame.
+    pub fn update(&mut self, skin: &Skin, music: &mut MusicManager) {
+        let time = music.time;
+        for (step_time, step) in &self.steps {
+            if self.last_time < *step_time
+                && *step_time <= time
+                && let Some(sound) = step.sound(skin)
+            {
+                music.play_sound(sound);
+            }
+        }
+        self.last_time = time;
+    }
+
+    pub fn render(&self, skin: &Skin, time: f32) {
+        let Some(index) = self.steps.iter().rposition(|(t, _)| *t <= time) else {
+            return;
+        };
+        let (start, step) = self.steps[index];
+        let end = self
+            .steps
+            .get(index + 1)
+            .map_or(start + self.beat_length, |(t, _)| *t);
+        if time >= end {
+            return;
+        }
+
+        // Fades out over the second half of its time on screen
+        let progress = (time - start) / (end - start);
+        let alpha = (2. - 2. * progress).min(1.);
+
+        let texture = step.texture(skin);
+        let size = texture.size() * (screen_height() / 768.);
+        draw_texture_ex(
+            texture,
+            screen_width() / 2. - size.x / 2.,
+            screen_height() / 2. - size.y / 2.,
+            Color::new(1., 1., 1., alpha),
+            DrawTextureParams {
+                dest_size: Some(size),
+                ..Default::default()
+            },
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use crate::content::skin::Skin;
+
+use super::input::InputManager;
+
+const FIXED_TRAIL_LENGTH: usize = 8;
+const TRAIL_LIFETIME: f64 = 0.15;
+const SMOKE_LIFETIME: f64 = 8.;
+const SMOKE_FADE: f64 = 1.;
+const EXPANDED_SCALE: f32 = 1.3;
+
+pub struct CursorRenderer {
+    position: Vec2,
+    trail: VecDeque<(Vec2, f64)>,
+    smoke: Vec<(Vec2, f64)>,
+    last_smoke: Option<Vec2>,
+    expand: f32,
+}
+
+impl CursorRenderer {
+    pub fn new() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            trail: VecDeque::new(),
+            smoke: Vec::new(),
+            last_smoke: None,
+            expand: 1.,
+        }
+    }
+
+    pub fn update(&mut self, skin: &Skin, input: &InputManager, playfield: Rect) {
+        let now = get_time();
+        let scale = playfield.h / 384.;
+        let position = Vec2 {
+            x: input.cursor.x * scale + playfield.x,
+            y: input.cursor.y * scale + playfield.y,
+        };
+
+        // Fill in the path since the last frame so fast flicks don't leave gaps
+        let from = if self.trail.is_empty() {
+            position
+        } else {
+            self.position
+        };
+        let spacing = skin.cursor_trail.width() * scale / 2.5;
+        for point in interpolate(from, position, spacing) {
+            self.trail.push_back((point, now));
+        }
+
+        if skin.cursor_middle.is_some() {
+            while self.trail.len() > FIXED_TRAIL_LENGTH {
+                self.trail.pop_front();
+            }
+        } else {
+            while self
+                .trail
+                .front()
+                .is_some_and(|(_, t)| now - t > TRAIL_LIFETIME)
+            {
+                self.trail.pop_front();
+            }
+        }
+
+        if input.smoke_held() {
+            let from = self.last_smoke.unwrap_or(position);
+            let spacing = skin.cursor_smoke.width() * scale / 4.;
+            for point in interpolate(from, position, spacing) {
+                self.smoke.push((point, now));
+            }
+            self.last_smoke = Some(position);
+        } else {
+            self.last_smoke = None;
+        }
+        self.smoke.retain(|(_, t)| now - t < SMOKE_LIFETIME);
+
+        let target = if skin.config.cursor_expand && input.any_held() {
+            EXPANDED_SCALE
+        } else {
+            1.
+        };
+        self.expand += (target - self.expand) * (1. - (-get_frame_time() * 20.).exp());
+
+        self.position = position;
+    }
+
+    pub fn render(&self, skin: &Skin, playfield: Rect) {
+        let now = get_time();
+        let scale = playfield.h / 384.;
+
+        for (point, time) in &self.smoke {
+            let alpha = ((SMOKE_LIFETIME - (now - time)) / SMOKE_FADE).min(1.) as f32;
+            draw_centred(&skin.cursor_smoke, *point, scale, 0., alpha);
+        }
+
+        let trail_len = self.trail.len();
+        for (i, (point, time)) in self.trail.iter().enumerate() {
+            let alpha = if skin.cursor_middle.is_some() {
+                (i + 1) as f32 / trail_len as f32
+            } else {
+                (1. - (now - time) / TRAIL_LIFETIME) as f32
+            };
+            draw_centred(&skin.cursor_trail, *point, scale, 0., alpha);
+        }
+
+        let rotation = if skin.config.cursor_rotate {
+            (now * std::f64::consts::TAU / 10.) as f32
+        } else {
+            0.
+        };
+
+        let cursor_scale = scale * self.expand;
+        if skin.config.cursor_centre {
+            draw_centred(&skin.cursor, self.position, cursor_scale, rotation, 1.);
+        } else {
+            draw_texture_ex(
+                &skin.cursor,
+                self.position.x,
+                self.position.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(skin.cursor.size() * cursor_scale),
+                    rotation,
+                    pivot: Some(self.position),
+                    ..Default::default()
+                },
+            );
+        }
+
+        if let Some(middle) = &skin.cursor_middle {
+            draw_centred(middle, self.position, scale, 0., 1.);
+        }
+    }
+}
+
+fn draw_centred(texture: &Texture2D, position: Vec2, scale: f32, rotation: f32, alpha: f32) {
+    let size = texture.size() * scale;
+    draw_texture_ex(
+        texture,
+        position.x - size.x / 2.,
+        position.y - size.y / 2.,
+        Color::new(1., 1., 1., alpha),
+        DrawTextureParams {
+            dest_size: Some(size),
+            rotation,
+            ..Default::default()
+        },
+    );
+}
+
+/// Points from `from` (exclusive) to `to` (inclusive), at most `spacing` apart.
+fn interpolate(from: Vec2, to: Vec2, spacing: f32) -> Vec<Vec2> {
+    let steps = (from.distance(to) / spacing.max(1.)).ceil().max(1.) as usize;
+    (1..=steps)
+        .map(|i| from.lerp(to, i as f32 / steps as f32))
+        .collect()
+}
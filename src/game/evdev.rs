@@ -0,0 +1,314 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    time::{Duration, SystemTime},
+};
+
+use macroquad::prelude::*;
+
+use super::{
+    input::{InputAction, InputConfig, InputSource, MacroquadInput, RawInput},
+    music::MusicManager,
+};
+
+const EV_KEY: u16 = 0x01;
+const EVENT_SIZE: usize = 24;
+/// Frames a press may wait for the window to see it too before it is dropped.
+const CONFIRM_FRAMES: u32 = 2;
+
+struct PendingEvent {
+    action: InputAction,
+    pressed: bool,
+    stamp: SystemTime,
+    frame: u32,
+}
+
+/// Reads keys and buttons straight from `/dev/input` on one thread per device.
+/// Events keep the kernel timestamp, so presses are placed on the song clock
+/// at the moment they happened instead of when the next frame polls them.
+///
+/// Devices see every key on the system, so a press only counts once the window has received
+/// it as well, which keeps keys typed into other windows out. Bindings that no readable
+/// device reports are read through macroquad instead.
+pub struct EvdevInput {
+    receiver: Receiver<(u16, bool, SystemTime)>,
+    /// Bindings some readable device reports.
+    bindings: Vec<(InputAction, u16)>,
+    pending: VecDeque<PendingEvent>,
+    held: [bool; 4],
+    frame: u32,
+    fallback: MacroquadInput,
+    fallback_events: Vec<RawInput>,
+}
+
+impl EvdevInput {
+    /// Returns `None` when no readable device reports any of the bindings.
+    pub fn spawn(config: &InputConfig) -> Option<Self> {
+        let wanted = [
+            (InputAction::K1, key_code(config.k1)),
+            (InputAction::K2, key_code(config.k2)),
+            (InputAction::M1, button_code(config.m1)),
+            (InputAction::M2, button_code(config.m2)),
+        ];
+
+        let (sender, receiver) = mpsc::channel();
+        let mut bindings = Vec::new();
+
+        for entry in std::fs::read_dir("/dev/input").ok()?.flatten() {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if !name.starts_with("event") {
+                continue;
+            }
+
+            let capabilities = key_capabilities(&name);
+            let reported = wanted
+                .iter()
+                .filter_map(|(action, code)| Some((*action, (*code)?)))
+                .filter(|(_, code)| has_bit(&capabilities, *code))
+                .collect::<Vec<_>>();
+            if reported.is_empty() {
+                continue;
+            }
+
+            if let Ok(file) = File::open(entry.path()) {
+                let sender = sender.clone();
+                std::thread::spawn(move || read_device(file, sender));
+                for binding in reported {
+                    if !bindings.contains(&binding) {
+                        bindings.push(binding);
+                    }
+                }
+            }
+        }
+
+        if bindings.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            receiver,
+            bindings,
+            pending: VecDeque::new(),
+            held: [false; 4],
+            frame: 0,
+            fallback: MacroquadInput,
+            fallback_events: Vec::new(),
+        })
+    }
+
+    fn covers(&self, action: InputAction) -> bool {
+        self.bindings.iter().any(|(a, _)| *a == action)
+    }
+}
+
+/// The `EV_KEY` capability bitmap of an event device, as the words sysfs lists it in, most
+/// significant first. This is what `EVIOCGBIT` returns.
+fn key_capabilities(device: &str) -> Vec<u64> {
+    let path = Path::new("/sys/class/input")
+        .join(device)
+        .join("device/capabilities/key");
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|word| u64::from_str_radix(word, 16).ok())
+        .collect()
+}
+
+fn has_bit(words: &[u64], code: u16) -> bool {
+    let bits = usize::BITS as usize;
+    let word = code as usize / bits;
+    words
+        .len()
+        .checked_sub(word + 1)
+        .is_some_and(|i| words[i] & (1 << (code as usize % bits)) != 0)
+}
+
+/// Whether the window got this press too, meaning it had focus.
+fn window_saw(action: InputAction, config: &InputConfig) -> bool {
+    match action {
+        InputAction::K1 | InputAction::K2 => {
+            let key = if action == InputAction::K1 {
+                config.k1
+            } else {
+                config.k2
+            };
+            is_key_down(key) || is_key_pressed(key) || is_key_released(key)
+        }
+        InputAction::M1 | InputAction::M2 => {
+            let button = if action == InputAction::M1 {
+                config.m1
+            } else {
+                config.m2
+            };
+            is_mouse_button_down(button)
+                || is_mouse_button_pressed(button)
+                || is_mouse_button_released(button)
+        }
+    }
+}
+
+fn read_device(mut file: File, sender: Sender<(u16, bool, SystemTime)>) {
+    let mut buf = [0u8; EVENT_SIZE];
+
+    while file.read_exact(&mut buf).is_ok() {
+        let secs = i64::from_ne_bytes(buf[0..8].try_into().unwrap());
+        let micros = i64::from_ne_bytes(buf[8..16].try_into().unwrap());
+        let kind = u16::from_ne_bytes(buf[16..18].try_into().unwrap());
+        let code = u16::from_ne_bytes(buf[18..20].try_into().unwrap());
+        let value = i32::from_ne_bytes(buf[20..24].try_into().unwrap());
+
+        // value 2 is key repeat
+        if kind != EV_KEY || value > 1 {
+            continue;
+        }
+
+        let stamp = SystemTime::UNIX_EPOCH
+            + Duration::from_secs(secs as u64)
+            + Duration::from_micros(micros as u64);
+
+        if sender.send((code, value == 1, stamp)).is_err() {
+            break;
+        }
+    }
+}
+
+impl InputSource for EvdevInput {
+    fn poll(&mut self, config: &InputConfig, music: &MusicManager, out: &mut Vec<RawInput>) {
+        self.frame += 1;
+
+        for (code, pressed, stamp) in self.receiver.try_iter() {
+            for (action, bound) in &self.bindings {
+                if code == *bound {
+                    self.pending.push_back(PendingEvent {
+                        action: *action,
+                        pressed,
+                        stamp,
+                        frame: self.frame,
+                    });
+                }
+            }
+        }
+
+        // In order, so a release never overtakes the press it belongs to
+        while let Some(event) = self.pending.front() {
+            let held = &mut self.held[event.action.index()];
+            let keep = if event.pressed {
+                if !window_saw(event.action, config) {
+                    if self.frame - event.frame < CONFIRM_FRAMES {
+                        break;
+                    }
+                    false
+                } else {
+                    *held = true;
+                    true
+                }
+            } else {
+                std::mem::replace(held, false)
+            };
+
+            if keep {
+                out.push(RawInput {
+                    action: event.action,
+                    pressed: event.pressed,
+                    time: music.time_at(event.stamp),
+                });
+            }
+            self.pending.pop_front();
+        }
+
+        self.fallback_events.clear();
+        self.fallback.poll(config, music, &mut self.fallback_events);
+        out.extend(
+            self.fallback_events
+                .iter()
+                .filter(|raw| !self.covers(raw.action)),
+        );
+    }
+
+    fn cursor(&self) -> Vec2 {
+        mouse_position().into()
+    }
+
+    fn clear(&mut self) {
+        self.receiver.try_iter().for_each(drop);
+        self.pending.clear();
+        self.held = [false; 4];
+    }
+}
+
+fn button_code(button: MouseButton) -> Option<u16> {
+    match button {
+        MouseButton::Left => Some(0x110),
+        MouseButton::Right => Some(0x111),
+        MouseButton::Middle => Some(0x112),
+        _ => None,
+    }
+}
+
+fn key_code(key: KeyCode) -> Option<u16> {
+    let code = match key {
+        KeyCode::Escape => 1,
+        KeyCode::Key1 => 2,
+        KeyCode::Key2 => 3,
+        KeyCode::Key3 => 4,
+        KeyCode::Key4 => 5,
+        KeyCode::Key5 => 6,
+        KeyCode::Key6 => 7,
+        KeyCode::Key7 => 8,
+        KeyCode::Key8 => 9,
+        KeyCode::Key9 => 10,
+        KeyCode::Key0 => 11,
+        KeyCode::Minus => 12,
+        KeyCode::Equal => 13,
+        KeyCode::Tab => 15,
+        KeyCode::Q => 16,
+        KeyCode::W => 17,
+        KeyCode::E => 18,
+        KeyCode::R => 19,
+        KeyCode::T => 20,
+        KeyCode::Y => 21,
+        KeyCode::U => 22,
+        KeyCode::I => 23,
+        KeyCode::O => 24,
+        KeyCode::P => 25,
+        KeyCode::LeftBracket => 26,
+        KeyCode::RightBracket => 27,
+        KeyCode::Enter => 28,
+        KeyCode::LeftControl => 29,
+        KeyCode::A => 30,
+        KeyCode::S => 31,
+        KeyCode::D => 32,
+        KeyCode::F => 33,
+        KeyCode::G => 34,
+        KeyCode::H => 35,
+        KeyCode::J => 36,
+        KeyCode::K => 37,
+        KeyCode::L => 38,
+        KeyCode::Semicolon => 39,
+        KeyCode::Apostrophe => 40,
+        KeyCode::GraveAccent => 41,
+        KeyCode::LeftShift => 42,
+        KeyCode::Backslash => 43,
+        KeyCode::Z => 44,
+        KeyCode::X => 45,
+        KeyCode::C => 46,
+        KeyCode::V => 47,
+        KeyCode::B => 48,
+        KeyCode::N => 49,
+        KeyCode::M => 50,
+        KeyCode::Comma => 51,
+        KeyCode::Period => 52,
+        KeyCode::Slash => 53,
+        KeyCode::RightShift => 54,
+        KeyCode::LeftAlt => 56,
+        KeyCode::Space => 57,
+        _ => return None,
+    };
+
+    Some(code)
+}
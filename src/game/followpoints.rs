@@ -0,0 +1,126 @@
+use macroquad::prelude::*;
+
+use crate::content::skin::Skin;
+
+pub struct FollowPointRenderer {
+    lines: Vec<FollowPointLine>,
+    last_end: Option<(Vec2, f32)>,
+    preemt: f32,
+    fade_duration: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FollowPointLine {
+    start: Vec2,
+    end: Vec2,
+    start_time: f32,
+    end_time: f32,
+}
+
+impl FollowPointRenderer {
+    pub fn new(preemt: f32, fade_in: f32) -> Self {
+        Self {
+            lines: Vec::new(),
+            last_end: None,
+            preemt,
+            fade_duration: fade_in.min(400.),
+        }
+    }
+
+    /// Registers a newly spawned object, connecting it to the previous object unless it
+    /// starts a new combo. Positions are in screen space.
+    pub fn connect(
+        &mut self,
+        new_combo: bool,
+        start: Vec2,
+        start_time: f32,
+        end: Vec2,
+        end_time: f32,
+    ) {
+        if let Some((prev_end, prev_end_time)) = self.last_end
+            && !new_combo
+        {
+            self.lines.push(FollowPointLine {
+                start: prev_end,
+                end: start,
+                start_time: prev_end_time,
+                end_time: start_time,
+            });
+        }
+
+        self.last_end = Some((end, end_time));
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.last_end = None;
+    }
+
+    /// Spinners are never connected to their neighbours.
+    pub fn break_chain(&mut self) {
+        self.last_end = None;
+    }
+
+    pub fn despawn(&mut self, current_time: f32) {
+        let fade_duration = self.fade_duration;
+        self.lines
+            .retain(|l| l.end_time + fade_duration > current_time);
+    }
+
+    pub fn render(&self, skin: &Skin, current_time: f32, playfield: Rect) {
+        let scale = playfield.h / 384.;
+        let spacing = 32. * scale;
+
+        let frame_time = 1000. / skin.follow_points.len() as f32;
+        let frame = (current_time.max(0.) / frame_time) as usize % skin.follow_points.len();
+        let texture = &skin.follow_points[frame];
+        let size = texture.size() * scale;
+
+        for line in &self.lines {
+            let delta = line.end - line.start;
+            let distance = delta.length();
+            let duration = line.end_time - line.start_time;
+            let rotation = delta.y.atan2(delta.x);
+
+            let mut d = spacing * 1.5;
+            while d < distance - spacing {
+                let fraction = d / distance;
+                d += spacing;
+
+                let fade_out_time = line.start_time + fraction * duration;
+                let fade_in_time = fade_out_time - self.preemt;
+
+                if current_time < fade_in_time || current_time > fade_out_time + self.fade_duration
+                {
+                    continue;
+                }
+
+                let progress = ((current_time - fade_in_time) / self.fade_duration).min(1.);
+                let eased = 1. - (1. - progress).powi(2);
+
+                let alpha = if current_time < fade_out_time {
+                    progress
+                } else {
+                    1. - (current_time - fade_out_time) / self.fade_duration
+                };
+
+                let from = line.start + delta * (fraction - 0.1);
+                let to = line.start + delta * fraction;
+                let position = from.lerp(to, eased);
+                let point_size = size * (1.5 - 0.5 * eased);
+
+                draw_texture_ex(
+                    texture,
+                    position.x - point_size.x / 2.,
+                    position.y - point_size.y / 2.,
+                    Color::new(1., 1., 1., alpha),
+                    DrawTextureParams {
+                        dest_size: Some(point_size),
+                        rotation,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use macroquad::prelude::*;
+
+use super::judgement::{HitResult, HitWindows, Judgement};
+
+const GREAT_COLOUR: Color = color_u8!(0x32, 0xBC, 0xE7, 0xff);
+const OK_COLOUR: Color = color_u8!(0x57, 0xE3, 0x13, 0xff);
+const MEH_COLOUR: Color = color_u8!(0xDA, 0xAE, 0x46, 0xff);
+const TICK_LIFETIME: f32 = 3000.;
+/// Width of the meter per ms of offset, before HUD scaling.
+const PIXELS_PER_MS: f32 = 0.8;
+/// How much each hit moves the average marker towards its offset.
+const AVERAGE_WEIGHT: f32 = 0.1;
+
+struct Tick {
+    offset: f32,
+    judgement: Judgement,
+    time: f32,
+}
+
+/// Shows how early or late each hit was against the hit windows, at the bottom of the screen.
+pub struct HitErrorMeter {
+    windows: HitWindows,
+    ticks: Vec<Tick>,
+    /// Exponential moving average of the offsets, `None` until the first hit.
+    average: Option<f32>,
+}
+
+impl HitErrorMeter {
+    pub fn new(windows: HitWindows) -> Self {
+        Self {
+            windows,
+            ticks: Vec::new(),
+            average: None,
+        }
+    }
+
+    pub fn update(&mut self, time: f32, results: &[HitResult]) {
+        self.ticks
+            .retain(|t| (0. ..TICK_LIFETIME).contains(&(time - t.time)));
+
+        for result in results.iter().filter(|r| r.judgement != Judgement::Miss) {
+            self.ticks.push(Tick {
+                offset: result.offset,
+                judgement: result.judgement,
+                time,
+            });
+            self.average = Some(self.average.map_or(result.offset, |average| {
+                average + (result.offset - average) * AVERAGE_WEIGHT
+            }));
+        }
+    }
+
+    /// Forgets every hit, for when the score starts over.
+    pub fn reset(&mut self) {
+        self.ticks.clear();
+        self.average = None;
+    }
+
+    pub fn render(&self, time: f32) {
+        let scale = screen_height() / 768.;
+        let centre = vec2(screen_width() / 2., screen_height() - 20. * scale);
+        let x = |offset: f32| centre.x + offset * PIXELS_PER_MS * scale;
+
+        // Widest first so the narrower windows are drawn over it
+        for (window, colour) in [
+            (self.windows.meh, MEH_COLOUR),
+            (self.windows.ok, OK_COLOUR),
+            (self.windows.great, GREAT_COLOUR),
+        ] {
+            let height = 6. * scale;
+            draw_rectangle(
+                x(-window),
+                centre.y - height / 2.,
+                x(window) - x(-window),
+                height,
+                Color { a: 0.5, ..colour },
+            );
+        }
+
+        for tick in &self.ticks {
+            let colour = match tick.judgement {
+                Judgement::Great => GREAT_COLOUR,
+                Judgement::Ok => OK_COLOUR,
+                _ => MEH_COLOUR,
+            };
+            let alpha = 1. - (time - tick.time) / TICK_LIFETIME;
+            let (width, height) = (2. * scale, 20. * scale);
+            draw_rectangle(
+                x(tick.offset) - width / 2.,
+                centre.y - height / 2.,
+                width,
+                height,
+                Color { a: alpha, ..colour },
+            );
+        }
+
+        draw_rectangle(
+            centre.x - scale,
+            centre.y - 12. * scale,
+            2. * scale,
+            24. * scale,
+            WHITE,
+        );
+
+        // Points down at the average, just above the ticks
+        if let Some(average) = self.average {
+            let tip = vec2(x(average), centre.y - 13. * scale);
+            draw_triangle(
+                tip,
+                tip + vec2(-6., -8.) * scale,
+                tip + vec2(6., -8.) * scale,
+                WHITE,
+            );
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use macroquad::prelude::*;
+
+use super::music::MusicManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputAction {
+    K1,
+    K2,
+    M1,
+    M2,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 4] = [
+        InputAction::K1,
+        InputAction::K2,
+        InputAction::M1,
+        InputAction::M2,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InputEvent {
+    pub action: InputAction,
+    pub pressed: bool,
+    /// Milliseconds on the game clock.
+    pub time: f32,
+    /// Cursor position in osu!pixels.
+    pub position: Vec2,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputConfig {
+    pub k1: KeyCode,
+    pub k2: KeyCode,
+    pub m1: MouseButton,
+    pub m2: MouseButton,
+    /// Held to draw smoke trails with the cursor.
+    pub smoke: KeyCode,
+    /// Restarts the map instantly.
+    pub retry: KeyCode,
+    /// Skips the intro or a break.
+    pub skip: KeyCode,
+    /// Screen area mapped absolutely onto the playfield, like a tablet area.
+    /// When unset the cursor maps 1:1 onto the playfield on screen.
+    pub tablet_area: Option<Rect>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            k1: KeyCode::Z,
+            k2: KeyCode::X,
+            m1: MouseButton::Left,
+            m2: MouseButton::Right,
+            smoke: KeyCode::C,
+            retry: KeyCode::GraveAccent,
+            skip: KeyCode::Space,
+            tablet_area: None,
+        }
+    }
+}
+
+/// Raw press or release from an input backend.
+#[derive(Debug, Clone, Copy)]
+pub struct RawInput {
+    pub action: InputAction,
+    pub pressed: bool,
+    pub time: f32,
+}
+
+pub trait InputSource {
+    /// Appends every press and release since the last poll, timestamped on the game clock.
+    fn poll(&mut self, config: &InputConfig, music: &MusicManager, out: &mut Vec<RawInput>);
+
+    /// Cursor position in screen pixels.
+    fn cursor(&self) -> Vec2;
+
+    /// Forgets events that have not been polled yet and which keys are down.
+    fn clear(&mut self) {}
+}
+
+/// Reads input through macroquad, so events are stamped when the frame polls them.
+pub struct MacroquadInput;
+
+impl InputSource for MacroquadInput {
+    fn poll(&mut self, config: &InputConfig, music: &MusicManager, out: &mut Vec<RawInput>) {
+        let time = music.now();
+
+        let keys = [(InputAction::K1, config.k1), (InputAction::K2, config.k2)];
+        for (action, key) in keys {
+            if is_key_pressed(key) {
+                out.push(RawInput {
+                    action,
+                    pressed: true,
+                    time,
+                });
+            }
+            if is_key_released(key) {
+                out.push(RawInput {
+                    action,
+                    pressed: false,
+                    time,
+                });
+            }
+        }
+
+        let buttons = [(InputAction::M1, config.m1), (InputAction::M2, config.m2)];
+        for (action, button) in buttons {
+            if is_mouse_button_pressed(button) {
+                out.push(RawInput {
+                    action,
+                    pressed: true,
+                    time,
+                });
+            }
+            if is_mouse_button_released(button) {
+                out.push(RawInput {
+                    action,
+                    pressed: false,
+                    time,
+                });
+            }
+        }
+    }
+
+    fn cursor(&self) -> Vec2 {
+        mouse_position().into()
+    }
+}
+
+pub struct InputManager {
+    config: InputConfig,
+    source: Box<dyn InputSource>,
+    queue: VecDeque<InputEvent>,
+    raw: Vec<RawInput>,
+    held: [bool; 4],
+    pub cursor: Vec2,
+}
+
+impl InputManager {
+    pub fn new(config: InputConfig, source: Box<dyn InputSource>) -> Self {
+        Self {
+            config,
+            source,
+            queue: VecDeque::new(),
+            raw: Vec::new(),
+            held: [false; 4],
+            cursor: Vec2::ZERO,
+        }
+    }
+
+    pub fn update(&mut self, music: &MusicManager, playfield: Rect) {
+        self.cursor = self.to_osu_pixels(self.source.cursor(), playfield);
+
+        self.raw.clear();
+        self.source.poll(&self.config, music, &mut self.raw);
+
+        for raw in &self.raw {
+            self.held[raw.action.index()] = raw.pressed;
+            self.queue.push_back(InputEvent {
+                action: raw.action,
+                pressed: raw.pressed,
+                time: raw.time,
+                position: self.cursor,
+            });
+        }
+    }
+
+    fn to_osu_pixels(&self, screen: Vec2, playfield: Rect) -> Vec2 {
+        match self.config.tablet_area {
+            Some(area) => Vec2 {
+                x: (screen.x - area.x) / area.w * 512.,
+                y: (screen.y - area.y) / area.h * 384.,
+            },
+            None => {
+                let scale = playfield.h / 384.;
+                Vec2 {
+                    x: (screen.x - playfield.x) / scale,
+                    y: (screen.y - playfield.y) / scale,
+                }
+            }
+        }
+    }
+
+    pub fn is_held(&self, action: InputAction) -> bool {
+        self.held[action.index()]
+    }
+
+    pub fn any_held(&self) -> bool {
+        self.held.iter().any(|h| *h)
+    }
+
+    pub fn smoke_held(&self) -> bool {
+        is_key_down(self.config.smoke)
+    }
+
+    pub fn retry_pressed(&self) -> bool {
+        is_key_pressed(self.config.retry)
+    }
+
+    pub fn skip_pressed(&self) -> bool {
+        is_key_pressed(self.config.skip)
+    }
+
+    /// Drops queued events, e.g. after seeking made their timestamps meaningless.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.held = [false; 4];
+        self.source.clear();
+    }
+
+    /// Events that have not been consumed by judgement yet, oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = &InputEvent> {
+        self.queue.iter()
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = InputEvent> + '_ {
+        self.queue.drain(..)
+    }
+}
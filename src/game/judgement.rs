@@ -0,0 +1,350 @@
+use macroquad::math::Vec2;
+
+use crate::content::beatmap::Difficulty;
+
+use super::mods::Mods;
+
+/// Ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Judgement {
+    Great,
+    Ok,
+    Meh,
+    Miss,
+}
+
+impl Judgement {
+    pub fn score(self) -> u32 {
+        match self {
+            Judgement::Great => 300,
+            Judgement::Ok => 100,
+            Judgement::Meh => 50,
+            Judgement::Miss => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HitWindows {
+    pub great: f32,
+    pub ok: f32,
+    pub meh: f32,
+}
+
+impl HitWindows {
+    pub fn new(overall_difficulty: f32) -> Self {
+        Self {
+            great: 80. - 6. * overall_difficulty,
+            ok: 140. - 8. * overall_difficulty,
+            meh: 200. - 10. * overall_difficulty,
+        }
+    }
+
+    /// Returns `None` when the offset falls outside every window.
+    pub fn judge(&self, offset: f32) -> Option<Judgement> {
+        let offset = offset.abs();
+        if offset <= self.great {
+            Some(Judgement::Great)
+        } else if offset <= self.ok {
+            Some(Judgement::Ok)
+        } else if offset <= self.meh {
+            Some(Judgement::Meh)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HitResult {
+    pub judgement: Judgement,
+    /// Time of the judged object in ms.
+    pub time: f32,
+    /// Hit time minus object time in ms, zero for misses.
+    pub offset: f32,
+    /// Where the object was in osu!pixels.
+    pub position: Vec2,
+    pub last_in_combo: bool,
+}
+
+/// Health and accuracy right after a result, for drawing them over time.
+#[derive(Debug, Clone, Copy)]
+pub struct ScorePoint {
+    pub time: f32,
+    pub health: f32,
+    pub accuracy: f32,
+}
+
+pub struct ScoreProcessor {
+    pub results: Vec<HitResult>,
+    pub combo: u32,
+    pub max_combo: u32,
+    pub score: u64,
+    /// Combos hit with nothing but 300s.
+    pub geki: u32,
+    /// Combos hit without any 50s or misses, but not all 300s.
+    pub katu: u32,
+    /// Between 0 and 1, starting full.
+    pub health: f32,
+    pub history: Vec<ScorePoint>,
+    /// Worst judgement in the current combo.
+    combo_worst: Judgement,
+    hp_drain_rate: f32,
+    score_multiplier: f32,
+}
+
+impl ScoreProcessor {
+    pub fn new(map: &Difficulty, mods: &Mods) -> Self {
+        Self {
+            results: Vec::new(),
+            combo: 0,
+            max_combo: 0,
+            score: 0,
+            geki: 0,
+            katu: 0,
+            health: 1.,
+            history: Vec::new(),
+            combo_worst: Judgement::Great,
+            hp_drain_rate: map.difficulty.hp_drain_rate,
+            score_multiplier: Self::difficulty_multiplier(map) * mods.score_multiplier(),
+        }
+    }
+
+    /// Like the official client's, from the difficulty settings and how dense the map is.
+    fn difficulty_multiplier(map: &Difficulty) -> f32 {
+        let settings = &map.difficulty;
+        let start = map.hit_objects.first().map_or(0., |o| o.time() as f32);
+        let end = map.object_info.last().map_or(0., |i| i.end_time);
+        let drain_seconds = ((end - start) / 1000.).max(1.);
+        let density = (map.hit_objects.len() as f32 / drain_seconds * 8.).clamp(0., 16.);
+
+        ((settings.hp_drain_rate + settings.circle_size + settings.overall_difficulty + density)
+            / 38.
+            * 5.)
+            .round()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            results: Vec::new(),
+            combo: 0,
+            max_combo: 0,
+            score: 0,
+            geki: 0,
+            katu: 0,
+            health: 1.,
+            history: Vec::new(),
+            combo_worst: Judgement::Great,
+            ..*self
+        };
+    }
+
+    pub fn apply(&mut self, result: HitResult) {
+        let value = result.judgement.score() as f32;
+        let combo_bonus = value * self.combo as f32 * self.score_multiplier / 25.;
+        self.score += (value + combo_bonus) as u64;
+
+        if result.judgement == Judgement::Miss {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+
+        self.combo_worst = self.combo_worst.max(result.judgement);
+        if result.last_in_combo {
+            match self.combo_worst {
+                Judgement::Great => self.geki += 1,
+                Judgement::Ok => self.katu += 1,
+                _ => {}
+            }
+            self.combo_worst = Judgement::Great;
+        }
+
+        self.health = (self.health + self.health_change(result.judgement)).clamp(0., 1.);
+        self.results.push(result);
+        self.history.push(ScorePoint {
+            time: result.time,
+            health: self.health,
+            accuracy: self.accuracy(),
+        });
+    }
+
+    pub fn count(&self, judgement: Judgement) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.judgement == judgement)
+            .count()
+    }
+
+    /// `silver` is for mods that hide objects, which turn S and SS grades silver.
+    pub fn grade(&self, silver: bool) -> Grade {
+        let total = self.results.len().max(1) as f32;
+        let great = self.count(Judgement::Great) as f32 / total;
+        let meh = self.count(Judgement::Meh) as f32 / total;
+        let no_misses = self.count(Judgement::Miss) == 0;
+
+        let grade = if great == 1. {
+            Grade::X
+        } else if great > 0.9 && meh < 0.01 && no_misses {
+            Grade::S
+        } else if (great > 0.8 && no_misses) || great > 0.9 {
+            Grade::A
+        } else if (great > 0.7 && no_misses) || great > 0.8 {
+            Grade::B
+        } else if great > 0.6 {
+            Grade::C
+        } else {
+            Grade::D
+        };
+
+        match (grade, silver) {
+            (Grade::X, true) => Grade::XH,
+            (Grade::S, true) => Grade::SH,
+            (grade, _) => grade,
+        }
+    }
+
+    /// Higher HP drain rates give less health for hits and take more for misses.
+    fn health_change(&self, judgement: Judgement) -> f32 {
+        let drain = self.hp_drain_rate / 10.;
+        match judgement {
+            Judgement::Great => 0.05 * (1. - 0.5 * drain),
+            Judgement::Ok => 0.02 * (1. - 0.5 * drain),
+            Judgement::Meh => 0.,
+            Judgement::Miss => -(0.02 + 0.12 * drain),
+        }
+    }
+
+    pub fn accuracy(&self) -> f32 {
+        accuracy(&self.results)
+    }
+
+    pub fn unstable_rate(&self) -> f32 {
+        unstable_rate(&self.results)
+    }
+}
+
+/// Accuracy of any set of results, 1 when there are none.
+pub fn accuracy(results: &[HitResult]) -> f32 {
+    if results.is_empty() {
+        return 1.;
+    }
+
+    let total: u32 = results.iter().map(|r| r.judgement.score()).sum();
+    total as f32 / (300. * results.len() as f32)
+}
+
+/// Ten times the standard deviation of the hit offsets, ignoring misses. 0 when nothing was
+/// hit.
+pub fn unstable_rate(results: &[HitResult]) -> f32 {
+    let offsets = results
+        .iter()
+        .filter(|r| r.judgement != Judgement::Miss)
+        .map(|r| r.offset)
+        .collect::<Vec<_>>();
+    if offsets.is_empty() {
+        return 0.;
+    }
+
+    let count = offsets.len() as f32;
+    let mean = offsets.iter().sum::<f32>() / count;
+    let variance = offsets.iter().map(|o| (o - mean).powi(2)).sum::<f32>() / count;
+    10. * variance.sqrt()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grade {
+    XH,
+    X,
+    SH,
+    S,
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Grade {
+    /// As used in skin file names like `ranking-XH.png`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Grade::XH => "XH",
+            Grade::X => "X",
+            Grade::SH => "SH",
+            Grade::S => "S",
+            Grade::A => "A",
+            Grade::B => "B",
+            Grade::C => "C",
+            Grade::D => "D",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(judgement: Judgement, offset: f32) -> HitResult {
+        HitResult {
+            judgement,
+            time: 0.,
+            offset,
+            position: Vec2::ZERO,
+            last_in_combo: false,
+        }
+    }
+
+    /// Grade after the given number of each judgement.
+    fn grade(counts: &[(Judgement, usize)], silver: bool) -> Grade {
+        let mut score = ScoreProcessor::new(&Difficulty::default(), &Mods { dt: false });
+        for &(judgement, count) in counts {
+            for _ in 0..count {
+                score.apply(result(judgement, 0.));
+            }
+        }
+        score.grade(silver)
+    }
+
+    #[test]
+    fn grades_follow_the_official_thresholds() {
+        use Judgement::*;
+
+        assert_eq!(grade(&[(Great, 100)], false), Grade::X);
+        assert_eq!(grade(&[(Great, 95), (Ok, 5)], false), Grade::S);
+        // 1% 50s is already too many for an S
+        assert_eq!(grade(&[(Great, 95), (Ok, 4), (Meh, 1)], false), Grade::A);
+        assert_eq!(grade(&[(Great, 95), (Miss, 5)], false), Grade::A);
+        assert_eq!(grade(&[(Great, 85), (Ok, 15)], false), Grade::A);
+        assert_eq!(grade(&[(Great, 85), (Ok, 14), (Miss, 1)], false), Grade::B);
+        assert_eq!(grade(&[(Great, 75), (Ok, 25)], false), Grade::B);
+        assert_eq!(grade(&[(Great, 75), (Ok, 24), (Miss, 1)], false), Grade::C);
+        assert_eq!(grade(&[(Great, 50), (Ok, 50)], false), Grade::D);
+    }
+
+    #[test]
+    fn silver_only_changes_the_top_grades() {
+        use Judgement::*;
+
+        assert_eq!(grade(&[(Great, 100)], true), Grade::XH);
+        assert_eq!(grade(&[(Great, 95), (Ok, 5)], true), Grade::SH);
+        assert_eq!(grade(&[(Great, 85), (Ok, 15)], true), Grade::A);
+    }
+
+    #[test]
+    fn unstable_rate_ignores_misses() {
+        let results = [
+            result(Judgement::Great, -10.),
+            result(Judgement::Ok, 10.),
+            result(Judgement::Miss, 0.),
+        ];
+        assert_eq!(unstable_rate(&results), 100.);
+
+        // A consistent offset is stable, however late it is
+        let late = [result(Judgement::Great, 15.); 3];
+        assert_eq!(unstable_rate(&late), 0.);
+
+        assert_eq!(unstable_rate(&[]), 0.);
+        assert_eq!(unstable_rate(&[result(Judgement::Miss, 0.)]), 0.);
+    }
+}
@@ -0,0 +1,98 @@
+use macroquad::prelude::*;
+
+use crate::content::skin::Skin;
+
+use super::input::{InputAction, InputManager};
+
+const KEY_COLOUR: Color = Color::new(1., 0.87, 0., 1.);
+const MOUSE_COLOUR: Color = Color::new(0.97, 0., 0.62, 1.);
+
+/// Shows which of K1/K2/M1/M2 are held and how often each was pressed this play.
+pub struct KeyOverlay {
+    counts: [u32; 4],
+}
+
+impl KeyOverlay {
+    pub fn new() -> Self {
+        Self { counts: [0; 4] }
+    }
+
+    pub fn reset(&mut self) {
+        self.counts = [0; 4];
+    }
+
+    /// Must run before judgement drains the input queue.
+    pub fn update(&mut self, input: &InputManager) {
+        for event in input.pending().filter(|e| e.pressed) {
+            self.counts[event.action.index()] += 1;
+        }
+    }
+
+    pub fn render(&self, skin: &Skin, input: &InputManager) {
+        let scale = screen_height() / 768.;
+
+        let background = &skin.input_overlay_background;
+        let size = background.size() * scale;
+        // The background is drawn rotated upright, so its height becomes its width on screen
+        let centre = Vec2::new(screen_width() - size.y / 2., screen_height() / 2.);
+
+        draw_texture_ex(
+            background,
+            centre.x - size.x / 2.,
+            centre.y - size.y / 2.,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(size),
+                rotation: std::f32::consts::FRAC_PI_2,
+                ..Default::default()
+            },
+        );
+
+        let key = &skin.input_overlay_key;
+        let spacing = size.x / 4.;
+
+        for (i, action) in InputAction::ALL.into_iter().enumerate() {
+            let position = Vec2::new(centre.x, centre.y + (i as f32 - 1.5) * spacing);
+            let held = input.is_held(action);
+
+            let (key_scale, tint) = match (held, action) {
+                (false, _) => (1., WHITE),
+                (true, InputAction::K1 | InputAction::K2) => (0.85, KEY_COLOUR),
+                (true, InputAction::M1 | InputAction::M2) => (0.85, MOUSE_COLOUR),
+            };
+
+            let key_size = key.size() * scale * key_scale;
+            draw_texture_ex(
+                key,
+                position.x - key_size.x / 2.,
+                position.y - key_size.y / 2.,
+                tint,
+                DrawTextureParams {
+                    dest_size: Some(key_size),
+                    ..Default::default()
+                },
+            );
+
+            let count = self.counts[action.index()];
+            let text = if count == 0 {
+                format!("{:?}", action)
+            } else {
+                count.to_string()
+            };
+
+            let font_size = (key_size.y * 0.45) as u16;
+            let dims = measure_text(&text, Some(&skin.font), font_size, 1.0);
+            draw_text_ex(
+                &text,
+                position.x - dims.width / 2.,
+                position.y + dims.height / 2.,
+                TextParams {
+                    font_size,
+                    font: Some(&skin.font),
+                    color: skin.config.input_overlay_text,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use macroquad::prelude::*;
+
+use crate::content::skin::Skin;
+
+use super::{
+    judgement::{HitResult, Judgement},
+    timing::TimingPointManager,
+};
+
+const PARTICLE_LIFETIME: f32 = 600.;
+const PARTICLES_PER_HIT: usize = 6;
+/// Speed of particles in osu!pixels per ms.
+const PARTICLE_SPEED: (f32, f32) = (0.05, 0.2);
+/// How much bigger hit circles get at the start of a beat.
+const PULSE_SCALE: f32 = 0.06;
+const FLASH_ALPHA: f32 = 0.15;
+
+struct Particle {
+    /// In osu!pixels.
+    position: Vec2,
+    velocity: Vec2,
+    rotation: f32,
+    spawn_time: f32,
+}
+
+/// Makes kiai sections pulse and sparkle.
+pub struct KiaiEffects {
+    particles: Vec<Particle>,
+    /// 1 on a beat, falling to 0 just before the next one. Always 0 outside kiai.
+    beat: f32,
+    /// Like `beat`, but only during the first beat of each measure.
+    measure: f32,
+}
+
+impl KiaiEffects {
+    pub fn new() -> Self {
+        Self {
+            particles: Vec::new(),
+            beat: 0.,
+            measure: 0.,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.particles.clear();
+    }
+
+    pub fn update(&mut self, timing: &TimingPointManager, time: f32, results: &[HitResult]) {
+        self.particles
+            .retain(|p| (0. ..PARTICLE_LIFETIME).contains(&(time - p.spawn_time)));
+
+        if !timing.current.kiai {
+            self.beat = 0.;
+            self.measure = 0.;
+            return;
+        }
+
+        let beat = timing.beat_at(time);
+        self.beat = (1. - beat.rem_euclid(1.)).powi(2);
+        let first_beat =
+            timing.measure_at(time).rem_euclid(1.) < 1. / timing.current.meter() as f32;
+        self.measure = if first_beat { self.beat } else { 0. };
+
+        for result in results.iter().filter(|r| r.judgement != Judgement::Miss) {
+            for _ in 0..PARTICLES_PER_HIT {
+                let angle = rand::gen_range(0., std::f32::consts::TAU);
+                let speed = rand::gen_range(PARTICLE_SPEED.0, PARTICLE_SPEED.1);
+                self.particles.push(Particle {
+                    position: result.position,
+                    velocity: Vec2::from_angle(angle) * speed,
+                    rotation: rand::gen_range(0., std::f32::consts::TAU),
+                    spawn_time: time,
+                });
+            }
+        }
+    }
+
+    /// Scale for hit circles.
+    pub fn pulse(&self) -> f32 {
+        1. + PULSE_SCALE * self.beat
+    }
+
+    pub fn render_border(&self, playfield: Rect) {
+        let grow = 4. * self.beat;
+        draw_rectangle_lines(
+            playfield.x - grow,
+            playfield.y - grow,
+            playfield.w + 2. * grow,
+            playfield.h + 2. * grow,
+            3. + grow,
+            WHITE,
+        );
+    }
+
+    pub fn render(&self, skin: &Skin, time: f32, playfield: Rect) {
+        let scale = playfield.h / 384.;
+
+        for particle in &self.particles {
+            let age = time - particle.spawn_time;
+            let position = particle.position + particle.velocity * age;
+            let size = skin.star2.size() * scale * 0.5;
+            draw_texture_ex(
+                &skin.star2,
+                playfield.x + position.x * scale - size.x / 2.,
+                playfield.y + position.y * scale - size.y / 2.,
+                Color::new(1., 1., 1., 1. - age / PARTICLE_LIFETIME),
+                DrawTextureParams {
+                    dest_size: Some(size),
+                    rotation: particle.rotation,
+                    ..Default::default()
+                },
+            );
+        }
+
+        if self.measure > 0. {
+            draw_rectangle(
+                0.,
+                0.,
+                screen_width(),
+                screen_height(),
+                Color::new(1., 1., 1., FLASH_ALPHA * self.measure),
+            );
+        }
+    }
+}
@@ -1,14 +1,39 @@
+use breaks::BreakOverlay;
+use countdown::CountdownRenderer;
+use cursor::CursorRenderer;
+use hiterror::HitErrorMeter;
+use input::{InputConfig, InputManager, InputSource, MacroquadInput};
+use judgement::{HitWindows, ScoreProcessor};
+use keyoverlay::KeyOverlay;
+use kiai::KiaiEffects;
 use mods::Mods;
 use music::MusicManager;
 use notes::NoteSpawner;
+use practice::{Practice, PracticeAction};
+use results::ResultsScreen;
+use skip::SkipButton;
 use timing::TimingPointManager;
 
 use crate::content::{beatmap::Difficulty, skin::Skin};
 
 use macroquad::prelude::*;
 
+mod breaks;
+mod countdown;
+mod cursor;
+#[cfg(target_os = "linux")]
+mod evdev;
+mod followpoints;
+mod hiterror;
+pub mod input;
+mod judgement;
+mod keyoverlay;
+mod kiai;
 mod music;
 mod notes;
+pub mod practice;
+mod results;
+mod skip;
 mod timing;
 mod mods;
 
@@ -16,17 +41,44 @@ pub struct Game {
     music: MusicManager,
     notes: NoteSpawner,
     timing: TimingPointManager,
+    input: InputManager,
+    score: ScoreProcessor,
+    cursor: CursorRenderer,
+    key_overlay: KeyOverlay,
+    countdown: CountdownRenderer,
+    breaks: BreakOverlay,
+    kiai: KiaiEffects,
+    hit_error: HitErrorMeter,
     mods: Mods,
+    practice: Option<Practice>,
+    skip: SkipButton,
+    /// Where the game clock starts, before zero when the map has a lead-in.
+    start_time: f32,
+    /// When the last object ends.
+    end_time: f32,
     title: String,
     playfield: Rect,
     skin: Skin,
 }
 
 impl Game {
-    pub fn new(skin: &Skin, difficulty: &Difficulty) -> Self {
-        let music = MusicManager::new(&difficulty.audio_bytes);
-        let notes = NoteSpawner::new(difficulty.hit_objects.clone(), &difficulty.difficulty);
+    /// How long after the last object the results screen comes up.
+    const RESULTS_DELAY: f32 = 1000.;
+
+    pub fn new(skin: &Skin, difficulty: &Difficulty, input_config: InputConfig) -> Self {
+        let music = MusicManager::new(difficulty.audio().expect("Failed to read audio"));
+        let notes = NoteSpawner::new(difficulty);
         let timing = TimingPointManager::new(difficulty.timing_points.clone());
+        let input = InputManager::new(input_config.clone(), Self::input_source(&input_config));
+        let countdown = CountdownRenderer::new(difficulty);
+        let start_time =
+            SkipButton::start_time(difficulty).min(countdown.start_time().unwrap_or(f32::INFINITY));
+        let end_time = difficulty
+            .object_info
+            .iter()
+            .map(|i| i.end_time)
+            .fold(0., f32::max);
+        let mods = Mods { dt: false };
 
         let h = screen_height() * 0.8;
         let w = h * (4. / 3.);
@@ -43,9 +95,21 @@ impl Game {
             notes,
             playfield,
             timing,
-            mods: Mods {
-                dt: false,
-            },
+            input,
+            score: ScoreProcessor::new(difficulty, &mods),
+            cursor: CursorRenderer::new(),
+            key_overlay: KeyOverlay::new(),
+            countdown,
+            breaks: BreakOverlay::new(difficulty),
+            kiai: KiaiEffects::new(),
+            hit_error: HitErrorMeter::new(HitWindows::new(
+                difficulty.difficulty.overall_difficulty,
+            )),
+            mods,
+            practice: None,
+            skip: SkipButton::new(difficulty, start_time),
+            start_time,
+            end_time,
             title: format!(
                 "{}[{}]",
                 difficulty.metadata.title.clone(),
@@ -54,15 +118,119 @@ impl Game {
         }
     }
 
+    /// Prefers a dedicated input thread so presses are not quantised to frames.
+    fn input_source(config: &InputConfig) -> Box<dyn InputSource> {
+        #[cfg(target_os = "linux")]
+        if let Some(source) = evdev::EvdevInput::spawn(config) {
+            return Box::new(source);
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        let _ = config;
+
+        Box::new(MacroquadInput)
+    }
+
+    pub fn enable_practice(&mut self, practice: Practice) {
+        self.practice = Some(practice);
+    }
+
+    /// Jumps anywhere in the song, keeping the score.
+    pub fn seek(&mut self, time: f32) {
+        self.music.seek(time);
+        self.timing.seek(time);
+        self.notes.seek(time);
+        self.countdown.seek(time);
+        self.breaks.seek(time);
+        self.input.clear();
+    }
+
+    /// Starts over from the beginning without reloading anything.
+    pub fn restart(&mut self) {
+        self.score.reset();
+        self.hit_error.reset();
+        self.key_overlay.reset();
+        self.kiai.reset();
+        if let Some(practice) = &mut self.practice {
+            practice.reset();
+        }
+        self.seek(self.start_time);
+    }
+
+    /// Shows the results until the player retries, returning whether they did.
+    async fn show_results(&mut self) -> bool {
+        let results = ResultsScreen::new(&self.title, &self.score, &self.mods);
+        show_mouse(true);
+
+        loop {
+            if self.input.retry_pressed() {
+                show_mouse(false);
+                self.restart();
+                return true;
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                return false;
+            }
+
+            results.render(&self.skin, &self.score);
+            next_frame().await;
+        }
+    }
+
     pub async fn play(&mut self) {
-        self.music.play(&self.mods);
+        self.music.play(&self.mods, self.start_time);
+        show_mouse(false);
 
         loop {
+            if self.input.retry_pressed() {
+                self.restart();
+            }
+
             self.music.update();
+
+            if self.notes.finished() && self.music.time > self.end_time + Self::RESULTS_DELAY {
+                if self.show_results().await {
+                    continue;
+                }
+                return;
+            }
+
+            let time = self.music.time;
+            match self
+                .practice
+                .as_mut()
+                .and_then(|p| p.update(time, &self.score))
+            {
+                Some(PracticeAction::Seek(time)) => self.seek(time),
+                Some(PracticeAction::Rate(rate)) => self.music.set_speed(self.mods.speed() * rate),
+                None => {}
+            }
+            if let Some(target) = self.skip.update(&self.skin, &self.input, self.music.time) {
+                self.seek(target);
+            }
+
             self.timing.update(&self.music);
+            self.countdown.update(&self.skin, &mut self.music);
+
+            self.input.update(&self.music, self.playfield);
+            self.key_overlay.update(&self.input);
+
+            self.notes.update(self.playfield, &self.music);
+            let results = self.notes.judge(&mut self.input, &self.music);
+            for result in &results {
+                self.score.apply(*result);
+            }
+            self.breaks.update(&self.skin, &mut self.music, &self.score);
+            self.kiai.update(&self.timing, self.music.time, &results);
+            self.hit_error.update(self.music.time, &results);
 
-            self.notes.update(self.playfield, &self.music, &self.timing);
+            self.notes.set_pulse(self.kiai.pulse());
             self.notes.render(&self.skin, &self.music, self.playfield);
+            self.kiai
+                .render(&self.skin, self.music.time, self.playfield);
+            self.countdown.render(&self.skin, self.music.time);
+            self.breaks
+                .render(&self.skin, self.music.time, self.playfield);
 
             draw_text(
                 &format!("{}", self.title),
@@ -73,7 +241,7 @@ impl Game {
             );
 
             draw_text(
-                &format!("time: {:.2}", self.music.time.as_secs_f64()),
+                &format!("time: {:.2}", self.music.time / 1000.),
                 10.,
                 40.,
                 23.,
@@ -81,21 +249,41 @@ impl Game {
             );
 
             draw_text(
-                &format!("bpm: {:.2}", self.timing.bpm()),
+                &format!(
+                    "bpm: {:.2}  sv: {:.2}x",
+                    self.timing.bpm(),
+                    self.timing.current.velocity_multiplier()
+                ),
                 10.,
                 60.,
                 23.,
                 color_u8!(0xFF, 0x74, 0x6C, 0xff),
             );
 
-            draw_rectangle_lines(
-                self.playfield.x,
-                self.playfield.y,
-                self.playfield.w,
-                self.playfield.h,
-                3.,
-                WHITE,
+            draw_text(
+                &format!(
+                    "combo: {}x  acc: {:.2}%",
+                    self.score.combo,
+                    self.score.accuracy() * 100.
+                ),
+                10.,
+                80.,
+                23.,
+                color_u8!(0xFF, 0x74, 0x6C, 0xff),
             );
+
+            self.kiai.render_border(self.playfield);
+
+            self.key_overlay.render(&self.skin, &self.input);
+            self.hit_error.render(self.music.time);
+            if let Some(practice) = &self.practice {
+                practice.render();
+            }
+            self.skip.render(&self.skin, self.music.time);
+
+            self.cursor.update(&self.skin, &self.input, self.playfield);
+            self.cursor.render(&self.skin, self.playfield);
+
             next_frame().await;
         }
     }
@@ -1,3 +1,29 @@
 pub struct Mods {
     pub dt: bool,
-}
\ No newline at end of file
+}
+
+impl Mods {
+    /// Playback rate of the song.
+    pub fn speed(&self) -> f64 {
+        if self.dt { 1.5 } else { 1.0 }
+    }
+
+    pub fn score_multiplier(&self) -> f32 {
+        if self.dt { 1.12 } else { 1.0 }
+    }
+
+    /// Whether S and SS grades are silver, which mods that hide objects do. None of those
+    /// exist yet.
+    pub fn silver_grades(&self) -> bool {
+        false
+    }
+
+    /// Names of the enabled mods, as in `selection-mod-<name>.png`.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.dt {
+            names.push("doubletime");
+        }
+        names
+    }
+}
@@ -1,11 +1,12 @@
 use std::{
     io::Cursor,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use kira::{
-    AudioManager, AudioManagerSettings, DefaultBackend,
-    sound::static_sound::StaticSoundData,
+    AudioManager, AudioManagerSettings, DefaultBackend, StartTime, Tween,
+    sound::static_sound::{StaticSoundData, StaticSoundHandle},
 };
 
 use super::mods::Mods;
@@ -13,38 +14,89 @@ use super::mods::Mods;
 pub struct MusicManager {
     audio_manager: AudioManager,
     music_data: StaticSoundData,
+    handle: Option<StaticSoundHandle>,
     start_stamp: SystemTime,
     speed: f64,
-    pub time: Duration,
+    /// Song position in ms, negative before the song starts.
+    pub time: f32,
 }
 
 impl MusicManager {
-    pub fn new(audio_data: &Vec<u8>) -> Self {
+    pub fn new(audio_data: Arc<[u8]>) -> Self {
         let manager = AudioManager::<DefaultBackend>::new(AudioManagerSettings::default())
             .expect("Failed to create audio backend.");
-        let music = StaticSoundData::from_cursor(Cursor::new(audio_data.clone()))
+        let music = StaticSoundData::from_cursor(Cursor::new(audio_data))
             .expect("Failed to create sound data for music.");
 
         Self {
             audio_manager: manager,
             music_data: music,
+            handle: None,
             speed: 1.0,
             start_stamp: SystemTime::now(),
-            time: Duration::from_secs(0),
+            time: 0.,
         }
     }
 
-    pub fn play(&mut self, mods: &Mods) {
-        let mut music = self.music_data.clone();
-        if mods.dt {
-            self.speed = 1.5;
-            music = music.playback_rate(self.speed);
+    /// Starts the game clock at `start`, which is negative to give a lead-in before the song.
+    pub fn play(&mut self, mods: &Mods, start: f32) {
+        self.speed = mods.speed();
+        self.seek(start);
+    }
+
+    /// Changes the playback rate without losing the song position.
+    pub fn set_speed(&mut self, speed: f64) {
+        let time = self.now();
+        self.speed = speed;
+        self.seek(time);
+    }
+
+    /// Restarts the song from `time`, which also works after it has finished.
+    pub fn seek(&mut self, time: f32) {
+        if let Some(mut handle) = self.handle.take() {
+            handle.stop(Tween::default());
         }
-        self.audio_manager.play(music).unwrap();
-        self.start_stamp = SystemTime::now();
+
+        // Wall clock time from now until the song reaches `time`, negative if it already has.
+        let wait = -time as f64 / 1000. / self.speed;
+        let music = self.music_data.playback_rate(self.speed);
+        let music = if wait > 0. {
+            music.start_time(StartTime::Delayed(Duration::from_secs_f64(wait)))
+        } else {
+            music.start_position(time as f64 / 1000.)
+        };
+        self.handle = Some(self.audio_manager.play(music).unwrap());
+
+        let now = SystemTime::now();
+        self.start_stamp = if wait > 0. {
+            now + Duration::from_secs_f64(wait)
+        } else {
+            now.checked_sub(Duration::from_secs_f64(-wait))
+                .unwrap_or(now)
+        };
+        self.time = time;
+    }
+
+    /// Plays a sound effect over the song.
+    pub fn play_sound(&mut self, sound: &StaticSoundData) {
+        let _ = self.audio_manager.play(sound.clone());
     }
 
     pub fn update(&mut self) {
-        self.time = self.start_stamp.elapsed().unwrap().mul_f64(self.speed);
+        self.time = self.now();
+    }
+
+    /// Song position right now, rather than at the start of the frame.
+    pub fn now(&self) -> f32 {
+        self.time_at(SystemTime::now())
+    }
+
+    /// Song position at a wall clock timestamp, e.g. one recorded by the kernel.
+    pub fn time_at(&self, stamp: SystemTime) -> f32 {
+        let elapsed = match stamp.duration_since(self.start_stamp) {
+            Ok(elapsed) => elapsed.as_secs_f64(),
+            Err(e) => -e.duration().as_secs_f64(),
+        };
+        (elapsed * self.speed * 1000.) as f32
     }
 }
@@ -2,22 +2,30 @@ use macroquad::prelude::*;
 use num_integer::binomial;
 
 use crate::content::{
-    beatmap::{DifficultySection, HitObject, SliderType},
+    beatmap::{Difficulty, HitObject, ObjectInfo, SliderTiming, SliderType},
     skin::Skin,
 };
 
-use super::{music::MusicManager, timing::TimingPointManager};
+use super::{
+    followpoints::FollowPointRenderer,
+    input::InputManager,
+    judgement::{HitResult, HitWindows, Judgement},
+    music::MusicManager,
+};
 
 pub struct NoteSpawner {
     objs: Vec<HitObject>,
+    info: Vec<ObjectInfo>,
     index: usize,
     preemt: f32,
     fade_in: f32,
     cs: f32,
-    slider_multiplier: f32,
+    hit_windows: HitWindows,
     render_queue: Vec<RenderableObject>,
-    combo: usize,
     combo_colors: Vec<(f32, f32, f32)>,
+    follow_points: FollowPointRenderer,
+    /// Scale of hit circles, above 1 while they pulse to the beat.
+    pulse: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -31,32 +39,32 @@ pub enum RenderableObject {
 pub struct RenderableCircle {
     combo_color: (f32, f32, f32),
     combo: usize,
+    last_in_combo: bool,
     time: f32,
     x: f32,
     y: f32,
+    judgement: Option<Judgement>,
 }
 
 #[derive(Debug, Clone)]
 pub struct RenderableSlider {
     combo_color: (f32, f32, f32),
     combo: usize,
+    last_in_combo: bool,
     time: f32,
     x: f32,
     y: f32,
     curves: Vec<Vec2>,
     curve_type: SliderType,
     segments: Vec<Vec2>,
-    length: f32,
+    timing: SliderTiming,
     repeat: usize,
+    judgement: Option<Judgement>,
 }
 
 impl RenderableSlider {
-    pub fn total_duration(&self) -> f32 {
-        self.length * self.repeat as f32
-    }
-
     pub fn end_time(&self) -> f32 {
-        self.time + self.total_duration()
+        self.timing.end_time
     }
 }
 
@@ -65,17 +73,51 @@ pub struct RenderableSpinner {
     end_time: f32,
 }
 
+impl RenderableObject {
+    /// The object, if it can still be clicked.
+    fn hit_target(&mut self) -> Option<HitTarget<'_>> {
+        match self {
+            RenderableObject::Circle(obj) if obj.judgement.is_none() => Some(HitTarget {
+                position: Vec2::new(obj.x, obj.y),
+                time: obj.time,
+                last_in_combo: obj.last_in_combo,
+                judgement: &mut obj.judgement,
+            }),
+            RenderableObject::Slider(obj) if obj.judgement.is_none() => Some(HitTarget {
+                position: Vec2::new(obj.x, obj.y),
+                time: obj.time,
+                last_in_combo: obj.last_in_combo,
+                judgement: &mut obj.judgement,
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct HitTarget<'a> {
+    position: Vec2,
+    time: f32,
+    last_in_combo: bool,
+    judgement: &'a mut Option<Judgement>,
+}
+
+impl HitTarget<'_> {
+    fn judge(self, judgement: Judgement, offset: f32) -> HitResult {
+        *self.judgement = Some(judgement);
+        HitResult {
+            judgement,
+            time: self.time,
+            offset,
+            position: self.position,
+            last_in_combo: self.last_in_combo,
+        }
+    }
+}
+
 impl NoteSpawner {
-    pub fn new(hit_objects: Vec<HitObject>, difficulty: &DifficultySection) -> Self {
-        let preemt = if difficulty.approach_rate == 5. {
-            1200.
-        } else if difficulty.approach_rate < 5. {
-            1200. + 600. * (5. - difficulty.approach_rate) / 5.
-        } else if difficulty.approach_rate > 5. {
-            1200. - 750. * (difficulty.approach_rate - 5.) / 5.
-        } else {
-            panic!("sometihgn went very very wrong!");
-        };
+    pub fn new(map: &Difficulty) -> Self {
+        let difficulty = &map.difficulty;
+        let preemt = difficulty.preempt();
 
         let fade_in = if difficulty.approach_rate == 5. {
             800.
@@ -88,14 +130,16 @@ impl NoteSpawner {
         };
 
         Self {
-            objs: hit_objects,
+            objs: map.hit_objects.clone(),
+            info: map.object_info.clone(),
             render_queue: Vec::new(),
             index: 0,
             preemt,
             fade_in,
-            slider_multiplier: difficulty.slider_multiplier,
+            hit_windows: HitWindows::new(difficulty.overall_difficulty),
             cs: difficulty.circle_size,
-            combo: 1,
+            follow_points: FollowPointRenderer::new(preemt, fade_in),
+            pulse: 1.,
             combo_colors: vec![
                 (0.90, 0.94, 0.39),
                 (0.78, 0.69, 0.99),
@@ -114,15 +158,8 @@ impl NoteSpawner {
         }
     }
 
-    fn slider_length(&self, pixel_length: f32, timing_manager: &TimingPointManager) -> f32 {
-        let base_velocity = 100.0 * self.slider_multiplier;
-        let velocity_multiplier = timing_manager.velocity_multiplier();
-        let velocity = base_velocity * velocity_multiplier;
-        let beat_length = timing_manager.beat_length();
-
-        let length = (pixel_length / velocity) * beat_length;
-
-        length
+    pub fn set_pulse(&mut self, pulse: f32) {
+        self.pulse = pulse;
     }
 
     fn cs(&self, playfield: Rect) -> f32 {
@@ -182,91 +219,169 @@ impl NoteSpawner {
         }
     }
 
-    pub fn spawn(&mut self, playfield: Rect, music: &MusicManager, timing: &TimingPointManager) {
+    /// Objects stacked on top of each other are moved up and to the left.
+    fn stack_offset(&self, stack_height: i32) -> Vec2 {
+        let radius = (108.0 - 8.0 * self.cs) / 2.;
+        Vec2::splat(-(stack_height as f32) * radius / 10.)
+    }
+
+    /// Returns whether an object was spawned.
+    pub fn spawn(&mut self, playfield: Rect, music: &MusicManager) -> bool {
         let curr = &self.objs[self.index];
-        let combo_color = self.combo_colors[self.combo % self.combo_colors.len()];
+        if curr.time() as f32 - self.preemt > music.time {
+            return false;
+        }
+
+        let info = self.info[self.index].clone();
+        self.index += 1;
+
+        let combo_color = self.combo_colors[info.colour_index % self.combo_colors.len()];
+        let offset = self.stack_offset(info.stack_height);
+        let (x, y) = curr.position();
+        let origin = Vec2::new(x as f32, y as f32) + offset;
+
         match curr {
             HitObject::HitCircle(obj) => {
-                if obj.time as f32 - self.preemt <= music.time.as_millis() as f32 {
-                    self.index += 1;
-                    self.render_queue
-                        .push(RenderableObject::Circle(RenderableCircle {
-                            combo: self.combo,
-                            combo_color,
-                            time: obj.time as f32,
-                            x: obj.x as f32,
-                            y: obj.y as f32,
-                        }));
-                    if obj.new_combo {
-                        self.combo = 0;
-                    }
-                    self.combo += 1;
-                }
-            }
-            HitObject::Slider(obj) => {
-                if obj.time as f32 - self.preemt <= music.time.as_millis() as f32 {
-                    self.index += 1;
-                    let mut slider = RenderableSlider {
-                        combo: self.combo,
-                        segments: vec![],
+                let position = Self::map_coords(origin, playfield);
+                self.follow_points.connect(
+                    obj.new_combo,
+                    position,
+                    obj.time as f32,
+                    position,
+                    obj.time as f32,
+                );
+
+                self.render_queue
+                    .push(RenderableObject::Circle(RenderableCircle {
+                        combo: info.combo_number,
+                        last_in_combo: info.last_in_combo,
                         combo_color,
                         time: obj.time as f32,
-                        x: obj.x as f32,
-                        y: obj.y as f32,
-                        length: self.slider_length(obj.pixel_length, &timing),
-                        curves: obj
-                            .curve_points
-                            .iter()
-                            .map(|i| Vec2 {
-                                x: i.0 as f32,
-                                y: i.1 as f32,
-                            })
-                            .collect(),
-                        curve_type: obj.slider_type.clone(),
-                        repeat: obj.repeat as usize,
-                    };
-                    slider.segments = self.calculate_slider_segments(&slider, playfield);
-                    self.render_queue.push(RenderableObject::Slider(slider));
-                    if obj.new_combo {
-                        self.combo = 0;
-                    }
-                    self.combo += 1;
-                }
+                        x: origin.x,
+                        y: origin.y,
+                        judgement: None,
+                    }));
+            }
+            HitObject::Slider(obj) => {
+                let mut slider = RenderableSlider {
+                    combo: info.combo_number,
+                    last_in_combo: info.last_in_combo,
+                    segments: vec![],
+                    combo_color,
+                    time: obj.time as f32,
+                    x: origin.x,
+                    y: origin.y,
+                    timing: info.slider.clone().unwrap_or_default(),
+                    curves: obj
+                        .curve_points
+                        .iter()
+                        .map(|i| Vec2::new(i.0 as f32, i.1 as f32) + offset)
+                        .collect(),
+                    curve_type: obj.slider_type.clone(),
+                    repeat: obj.repeat as usize,
+                    judgement: None,
+                };
+                slider.segments = self.calculate_slider_segments(&slider, playfield);
+
+                let start = slider.segments[0];
+                let end = if slider.repeat.is_multiple_of(2) {
+                    start
+                } else {
+                    *slider.segments.last().unwrap()
+                };
+                self.follow_points.connect(
+                    obj.new_combo,
+                    start,
+                    slider.time,
+                    end,
+                    slider.end_time(),
+                );
+
+                self.render_queue.push(RenderableObject::Slider(slider));
             }
             HitObject::Spinner(obj) => {
-                if obj.time as f32 - self.preemt <= music.time.as_millis() as f32 {
-                    self.index += 1;
-                    self.render_queue
-                        .push(RenderableObject::Spinner(RenderableSpinner {
-                            end_time: obj.end_time as f32,
-                        }));
-                    if obj.new_combo {
-                        self.combo = 0;
-                    }
-                    self.combo += 1;
-                }
+                self.follow_points.break_chain();
+                self.render_queue
+                    .push(RenderableObject::Spinner(RenderableSpinner {
+                        end_time: obj.end_time as f32,
+                    }));
             }
-            _ => {}
+            HitObject::HoldNote(_) => {}
         }
+        true
+    }
+
+    /// Continues from `time` as if the map had been played up to there. Objects that already
+    /// started are skipped rather than missed.
+    pub fn seek(&mut self, time: f32) {
+        self.index = self.objs.partition_point(|o| (o.time() as f32) < time);
+        self.render_queue.clear();
+        self.follow_points.clear();
     }
 
     pub fn despawn(&mut self, music: &MusicManager) {
-        let current_time = music.time.as_millis() as f32;
+        let current_time = music.time;
 
         self.render_queue.retain(|o| match o {
-            RenderableObject::Circle(obj) => obj.time + 150.0 > current_time,
-            RenderableObject::Slider(obj) => obj.end_time() + 150.0 > current_time,
+            RenderableObject::Circle(obj) => {
+                obj.judgement.is_none() || obj.time + 150.0 > current_time
+            }
+            RenderableObject::Slider(obj) => {
+                obj.judgement.is_none() || obj.end_time() + 150.0 > current_time
+            }
             RenderableObject::Spinner(obj) => obj.end_time + 150.0 > current_time,
         });
+        self.follow_points.despawn(current_time);
     }
 
-    pub fn update(&mut self, playfield: Rect, music: &MusicManager, timing: &TimingPointManager) {
-        if self.index < self.objs.len() {
-            self.spawn(playfield, music, timing);
-        }
+    pub fn update(&mut self, playfield: Rect, music: &MusicManager) {
+        // After a seek several objects can be due in the same frame.
+        while self.index < self.objs.len() && self.spawn(playfield, music) {}
         self.despawn(music);
     }
 
+    /// Whether every object has been spawned, judged and faded out.
+    pub fn finished(&self) -> bool {
+        self.index >= self.objs.len() && self.render_queue.is_empty()
+    }
+
+    /// Consumes queued presses and judges them against the earliest unjudged object.
+    pub fn judge(&mut self, input: &mut InputManager, music: &MusicManager) -> Vec<HitResult> {
+        let mut results = Vec::new();
+        let radius = (108.0 - 8.0 * self.cs) / 2.;
+
+        for event in input.drain() {
+            if !event.pressed {
+                continue;
+            }
+
+            self.miss_expired(event.time, &mut results);
+
+            let hit_windows = self.hit_windows;
+            if let Some(target) = self.render_queue.iter_mut().find_map(|o| o.hit_target())
+                && event.position.distance(target.position) <= radius
+                && let Some(judgement) = hit_windows.judge(event.time - target.time)
+            {
+                let offset = event.time - target.time;
+                results.push(target.judge(judgement, offset));
+            }
+        }
+
+        self.miss_expired(music.time, &mut results);
+        results
+    }
+
+    fn miss_expired(&mut self, current_time: f32, results: &mut Vec<HitResult>) {
+        let late = self.hit_windows.meh;
+        for o in self.render_queue.iter_mut() {
+            if let Some(target) = o.hit_target()
+                && current_time > target.time + late
+            {
+                results.push(target.judge(Judgement::Miss, 0.));
+            }
+        }
+    }
+
     fn render_combo_number(
         &self,
         skin: &Skin,
@@ -378,7 +493,7 @@ impl NoteSpawner {
     ) {
         let coord = Self::map_coords(Vec2::new(circle.x, circle.y), playfield);
         let alpha = self.alpha(circle.time, current_time);
-        let cs = self.cs(playfield);
+        let cs = self.cs(playfield) * self.pulse;
 
         let circle_params = DrawTextureParams {
             dest_size: Some(Vec2::new(cs, cs)),
@@ -422,6 +537,8 @@ impl NoteSpawner {
         let color = self.color_with_alpha(slider.combo_color, alpha);
         let cs = self.cs(playfield);
         let radius = cs / 2.;
+        // Only the head pulses, the body keeps its size
+        let cs = cs * self.pulse;
 
         let start_pos = Self::map_coords(Vec2::new(slider.x, slider.y), playfield);
 
@@ -597,7 +714,9 @@ impl NoteSpawner {
     }
 
     pub fn render(&mut self, skin: &Skin, music: &MusicManager, playfield: Rect) {
-        let current_time = music.time.as_millis() as f32;
+        let current_time = music.time;
+
+        self.follow_points.render(skin, current_time, playfield);
 
         for o in self.render_queue.iter().rev() {
             match o {
@@ -0,0 +1,217 @@
+use macroquad::prelude::*;
+
+use super::judgement::{self, ScoreProcessor};
+
+const TEXT_COLOUR: Color = color_u8!(0xA7, 0xC7, 0xE7, 0xff);
+
+/// Keys for practice mode.
+#[derive(Debug, Clone)]
+pub struct PracticeKeys {
+    pub loop_start: KeyCode,
+    pub loop_end: KeyCode,
+    pub clear: KeyCode,
+    /// Loop the section before or after the current one, split at the map's bookmarks.
+    pub previous_bookmark: KeyCode,
+    pub next_bookmark: KeyCode,
+    pub slower: KeyCode,
+    pub faster: KeyCode,
+}
+
+impl Default for PracticeKeys {
+    fn default() -> Self {
+        PracticeKeys {
+            loop_start: KeyCode::LeftBracket,
+            loop_end: KeyCode::RightBracket,
+            clear: KeyCode::Backspace,
+            previous_bookmark: KeyCode::Left,
+            next_bookmark: KeyCode::Right,
+            slower: KeyCode::Minus,
+            faster: KeyCode::Equal,
+        }
+    }
+}
+
+/// What the game has to do for practice mode this frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PracticeAction {
+    /// Jump to a song position in ms.
+    Seek(f32),
+    /// Play at this fraction of the normal rate.
+    Rate(f64),
+}
+
+/// Loops a section of the map, keeping the accuracy of each repetition.
+pub struct Practice {
+    keys: PracticeKeys,
+    bookmarks: Vec<f32>,
+    section: Option<usize>,
+    pub start: Option<f32>,
+    pub end: Option<f32>,
+    /// Time played before the loop start on every repetition.
+    pub lead_in: f32,
+    pub rate: f64,
+    /// Index into the score's results where the current repetition began.
+    first_result: usize,
+    pub loops: Vec<f32>,
+}
+
+impl Practice {
+    const MIN_RATE: f64 = 0.5;
+    const RATE_STEP: f64 = 0.05;
+
+    pub fn new(bookmarks: &[i32], keys: PracticeKeys) -> Self {
+        Self {
+            keys,
+            bookmarks: bookmarks.iter().map(|b| *b as f32).collect(),
+            section: None,
+            start: None,
+            end: None,
+            lead_in: 1500.,
+            rate: 1.,
+            first_result: 0,
+            loops: Vec::new(),
+        }
+    }
+
+    /// The loop range, once both ends are set the right way round.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if end > start => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    pub fn update(&mut self, time: f32, score: &ScoreProcessor) -> Option<PracticeAction> {
+        if is_key_pressed(self.keys.slower) || is_key_pressed(self.keys.faster) {
+            let step = if is_key_pressed(self.keys.faster) {
+                Self::RATE_STEP
+            } else {
+                -Self::RATE_STEP
+            };
+            self.rate = (self.rate + step).clamp(Self::MIN_RATE, 1.);
+            return Some(PracticeAction::Rate(self.rate));
+        }
+
+        if is_key_pressed(self.keys.clear) {
+            self.set_range(None, None, score);
+            return None;
+        }
+
+        if is_key_pressed(self.keys.loop_start) {
+            self.set_range(Some(time), self.end, score);
+            return None;
+        }
+
+        if is_key_pressed(self.keys.loop_end) {
+            self.set_range(self.start, Some(time), score);
+            return self.restart_loop(score);
+        }
+
+        let step = if is_key_pressed(self.keys.next_bookmark) {
+            Some(1)
+        } else if is_key_pressed(self.keys.previous_bookmark) {
+            Some(-1)
+        } else {
+            None
+        };
+        if let Some(step) = step
+            && let Some(section) = self.step_section(step, time)
+        {
+            let start = section.checked_sub(1).map_or(0., |i| self.bookmarks[i]);
+            let end = self.bookmarks.get(section).copied();
+            self.set_range(Some(start), end, score);
+            self.section = Some(section);
+            // The section after the last bookmark has no end, so it only plays once.
+            return Some(PracticeAction::Seek((start - self.lead_in).max(0.)));
+        }
+
+        match self.range() {
+            Some((start, end)) if time >= end => {
+                let loop_results = score
+                    .results
+                    .get(self.first_result..)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|r| r.time >= start && r.time < end)
+                    .copied()
+                    .collect::<Vec<_>>();
+                self.loops.push(judgement::accuracy(&loop_results));
+                self.restart_loop(score)
+            }
+            _ => None,
+        }
+    }
+
+    /// Forgets the repetitions played so far, for when the score starts over. The loop range
+    /// and rate are kept.
+    pub fn reset(&mut self) {
+        self.first_result = 0;
+        self.loops.clear();
+    }
+
+    /// Sections are the spans between consecutive bookmarks, plus the ones before the first
+    /// and after the last.
+    fn step_section(&self, step: isize, time: f32) -> Option<usize> {
+        if self.bookmarks.is_empty() {
+            return None;
+        }
+
+        let next = match self.section {
+            Some(section) => section.checked_add_signed(step)?,
+            None => self.bookmarks.partition_point(|b| *b <= time),
+        };
+        (next <= self.bookmarks.len()).then_some(next)
+    }
+
+    fn set_range(&mut self, start: Option<f32>, end: Option<f32>, score: &ScoreProcessor) {
+        if (start, end) != (self.start, self.end) {
+            self.loops.clear();
+        }
+        self.start = start;
+        self.end = end;
+        self.section = None;
+        self.first_result = score.results.len();
+    }
+
+    fn restart_loop(&mut self, score: &ScoreProcessor) -> Option<PracticeAction> {
+        let (start, _) = self.range()?;
+        self.first_result = score.results.len();
+        Some(PracticeAction::Seek((start - self.lead_in).max(0.)))
+    }
+
+    pub fn render(&self) {
+        let scale = screen_height() / 768.;
+        let font_size = 23. * scale;
+        let format_time = |t: Option<f32>| t.map_or("-".into(), |t| format!("{:.2}s", t / 1000.));
+
+        draw_text(
+            &format!(
+                "practice  A {}  B {}  rate {:.2}x",
+                format_time(self.start),
+                format_time(self.end),
+                self.rate
+            ),
+            10.,
+            screen_height() - 40. * scale,
+            font_size,
+            TEXT_COLOUR,
+        );
+
+        let loops = self
+            .loops
+            .iter()
+            .rev()
+            .take(8)
+            .rev()
+            .map(|acc| format!("{:.2}%", acc * 100.))
+            .collect::<Vec<_>>()
+            .join("  ");
+        draw_text(
+            &format!("loops: {}  {}", self.loops.len(), loops),
+            10.,
+            screen_height() - 15. * scale,
+            font_size,
+            TEXT_COLOUR,
+        );
+    }
+}
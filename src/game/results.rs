@@ -0,0 +1,234 @@
+use macroquad::prelude::*;
+
+use crate::content::skin::Skin;
+
+use super::{
+    judgement::{Grade, Judgement, ScoreProcessor},
+    mods::Mods,
+};
+
+const TEXT_COLOUR: Color = color_u8!(0xA7, 0xC7, 0xE7, 0xff);
+const HEALTH_COLOUR: Color = color_u8!(0x99, 0xEE, 0x44, 0xff);
+const LOW_HEALTH_COLOUR: Color = color_u8!(0xFF, 0x44, 0x44, 0xff);
+const ACCURACY_COLOUR: Color = color_u8!(0xFF, 0xCC, 0x22, 0xff);
+/// `scoreentry-*` glyphs are tiny, so everything drawn with them is scaled up.
+const DIGIT_SCALE: f32 = 2.5;
+const HIT_ICON_SCALE: f32 = 0.4;
+
+/// Shown once the song is over. Laid out like the official client's, in a 1024x768 space
+/// anchored to the left of the screen.
+pub struct ResultsScreen {
+    title: String,
+    grade: Grade,
+    mods: Vec<&'static str>,
+}
+
+impl ResultsScreen {
+    pub fn new(title: &str, score: &ScoreProcessor, mods: &Mods) -> Self {
+        Self {
+            title: title.to_string(),
+            grade: score.grade(mods.silver_grades()),
+            mods: mods.names(),
+        }
+    }
+
+    pub fn render(&self, skin: &Skin, score: &ScoreProcessor) {
+        let scale = screen_height() / 768.;
+        clear_background(BLACK);
+
+        let panel = &skin.ranking_panel;
+        let panel_size = panel.size() * (470. / panel.height()) * scale;
+        draw_texture_ex(
+            panel,
+            0.,
+            90. * scale,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(panel_size),
+                ..Default::default()
+            },
+        );
+
+        draw_text(&self.title, 10., 30. * scale, 32. * scale, TEXT_COLOUR);
+
+        draw_number(skin, &format!("{:08}", score.score), 180., 110., scale);
+
+        let rows = [
+            (Judgement::Great, &skin.hit300, score.geki, &skin.hit300g),
+            (Judgement::Ok, &skin.hit100, score.katu, &skin.hit100k),
+        ];
+        for (row, (judgement, icon, combo_count, combo_icon)) in rows.into_iter().enumerate() {
+            let y = 180. + row as f32 * 80.;
+            draw_count(skin, icon, score.count(judgement), 40., y, scale);
+            draw_count(skin, combo_icon, combo_count as usize, 320., y, scale);
+        }
+        draw_count(
+            skin,
+            &skin.hit50,
+            score.count(Judgement::Meh),
+            40.,
+            340.,
+            scale,
+        );
+        draw_count(
+            skin,
+            &skin.hit0,
+            score.count(Judgement::Miss),
+            320.,
+            340.,
+            scale,
+        );
+
+        draw_sprite(&skin.ranking_maxcombo, 20., 410., 1., scale);
+        draw_number(skin, &format!("{}x", score.max_combo), 30., 440., scale);
+        draw_sprite(&skin.ranking_accuracy, 290., 410., 1., scale);
+        draw_number(
+            skin,
+            &format!("{:.2}%", score.accuracy() * 100.),
+            300.,
+            440.,
+            scale,
+        );
+
+        draw_text(
+            &format!("unstable rate: {:.2}", score.unstable_rate()),
+            20. * scale,
+            535. * scale,
+            23. * scale,
+            TEXT_COLOUR,
+        );
+
+        self.render_graph(skin, score, scale);
+
+        let grade = &skin.ranking_grades[self.grade.name()];
+        let grade_size = grade.size() * 0.8 * scale;
+        draw_texture_ex(
+            grade,
+            screen_width() - 220. * scale - grade_size.x / 2.,
+            320. * scale - grade_size.y / 2.,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(grade_size),
+                ..Default::default()
+            },
+        );
+
+        // Right to left from the bottom right corner, like the official client
+        let mut x = screen_width() - 20. * scale;
+        for icon in self.mods.iter().filter_map(|name| skin.mod_icons.get(name)) {
+            let size = icon.size() * scale;
+            x -= size.x;
+            draw_texture_ex(
+                icon,
+                x,
+                600. * scale,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(size),
+                    ..Default::default()
+                },
+            );
+            x -= 10. * scale;
+        }
+
+        draw_text(
+            "retry: `  back: esc",
+            10.,
+            screen_height() - 15. * scale,
+            23. * scale,
+            TEXT_COLOUR,
+        );
+    }
+
+    /// Health and accuracy over the length of the play. Health turns red below half.
+    fn render_graph(&self, skin: &Skin, score: &ScoreProcessor, scale: f32) {
+        let graph = &skin.ranking_graph;
+        let bounds = Rect::new(
+            20. * scale,
+            560. * scale,
+            graph.width() * scale,
+            graph.height() * scale,
+        );
+        draw_texture_ex(
+            graph,
+            bounds.x,
+            bounds.y,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(bounds.size()),
+                ..Default::default()
+            },
+        );
+
+        let (Some(first), Some(last)) = (score.history.first(), score.history.last()) else {
+            return;
+        };
+        let duration = (last.time - first.time).max(1.);
+        // Keeps the lines clear of the sprite's border
+        let inner = Rect::new(
+            bounds.x + bounds.w * 0.05,
+            bounds.y + bounds.h * 0.1,
+            bounds.w * 0.9,
+            bounds.h * 0.8,
+        );
+        let point = |time: f32, value: f32| {
+            vec2(
+                inner.x + (time - first.time) / duration * inner.w,
+                inner.bottom() - value * inner.h,
+            )
+        };
+
+        for pair in score.history.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+
+            let colour = if to.health < 0.5 {
+                LOW_HEALTH_COLOUR
+            } else {
+                HEALTH_COLOUR
+            };
+            let a = point(from.time, from.health);
+            let b = point(to.time, to.health);
+            draw_line(a.x, a.y, b.x, b.y, 2. * scale, colour);
+
+            let a = point(from.time, from.accuracy);
+            let b = point(to.time, to.accuracy);
+            draw_line(a.x, a.y, b.x, b.y, 2. * scale, ACCURACY_COLOUR);
+        }
+    }
+}
+
+/// A sprite with its top left corner at (`x`, `y`) in the 1024x768 layout.
+fn draw_sprite(texture: &Texture2D, x: f32, y: f32, size: f32, scale: f32) {
+    draw_texture_ex(
+        texture,
+        x * scale,
+        y * scale,
+        WHITE,
+        DrawTextureParams {
+            dest_size: Some(texture.size() * size * scale),
+            ..Default::default()
+        },
+    );
+}
+
+/// Draws `text` with the `scoreentry-*` glyphs, skipping characters the skin has none for.
+fn draw_number(skin: &Skin, text: &str, x: f32, y: f32, scale: f32) {
+    let mut x = x;
+    for glyph in text.chars().filter_map(|c| skin.score_entry.get(&c)) {
+        draw_sprite(glyph, x, y, DIGIT_SCALE, scale);
+        x += glyph.width() * DIGIT_SCALE;
+    }
+}
+
+/// A judgement icon with how many times it was given next to it.
+fn draw_count(skin: &Skin, icon: &Texture2D, count: usize, x: f32, y: f32, scale: f32) {
+    let icon_size = icon.size() * HIT_ICON_SCALE;
+    draw_sprite(icon, x, y - icon_size.y / 2., HIT_ICON_SCALE, scale);
+    draw_number(
+        skin,
+        &format!("{}x", count),
+        x + icon_size.x,
+        y - 17.,
+        scale,
+    );
+}
@@ -0,0 +1,104 @@
+use macroquad::prelude::*;
+
+use crate::content::{
+    beatmap::{Difficulty, Event},
+    skin::Skin,
+};
+
+use super::input::InputManager;
+
+/// Lets the player jump over the intro and long breaks.
+pub struct SkipButton {
+    /// Spans that can be skipped, each skipping to its end.
+    ranges: Vec<(f32, f32)>,
+}
+
+impl SkipButton {
+    /// Time left before the next object after skipping.
+    const LEAD: f32 = 2000.;
+    /// Shorter gaps are not worth skipping.
+    const MIN_GAP: f32 = 1000.;
+
+    pub fn new(difficulty: &Difficulty, start: f32) -> Self {
+        let mut ranges = Vec::new();
+
+        if let Some(first) = difficulty.hit_objects.first() {
+            let first = first.time() as f32;
+            ranges.push((
+                start,
+                first - Self::LEAD.max(difficulty.difficulty.preempt()),
+            ));
+        }
+
+        for event in &difficulty.events {
+            if let Event::Break {
+                start_time,
+                end_time,
+            } = event
+            {
+                ranges.push((*start_time as f32, *end_time as f32 - Self::LEAD));
+            }
+        }
+
+        ranges.retain(|(from, to)| to - from >= Self::MIN_GAP);
+        Self { ranges }
+    }
+
+    /// Where the game clock should start so that both `AudioLeadIn` and the first object's
+    /// approach fit before the first object.
+    pub fn start_time(difficulty: &Difficulty) -> f32 {
+        let lead_in = -difficulty.general.audio_lead_in as f32;
+        let first = difficulty
+            .hit_objects
+            .first()
+            .map_or(0., |o| o.time() as f32 - difficulty.difficulty.preempt());
+        lead_in.min(first).min(0.)
+    }
+
+    /// End of the skippable span containing `time`, if any.
+    fn target(&self, time: f32) -> Option<f32> {
+        self.ranges
+            .iter()
+            .find(|(from, to)| (*from..*to).contains(&time))
+            .map(|(_, to)| *to)
+    }
+
+    fn bounds(&self, skin: &Skin) -> Rect {
+        let size = skin.play_skip.size() * (screen_height() / 768.);
+        Rect::new(
+            screen_width() - size.x,
+            screen_height() - size.y,
+            size.x,
+            size.y,
+        )
+    }
+
+    /// Returns the time to seek to when the player skipped.
+    pub fn update(&self, skin: &Skin, input: &InputManager, time: f32) -> Option<f32> {
+        let target = self.target(time)?;
+        let clicked = is_mouse_button_pressed(MouseButton::Left)
+            && self.bounds(skin).contains(mouse_position().into());
+
+        (input.skip_pressed() || clicked).then_some(target)
+    }
+
+    pub fn render(&self, skin: &Skin, time: f32) {
+        let Some(target) = self.target(time) else {
+            return;
+        };
+
+        // Fades out over the last half second it can be used
+        let alpha = ((target - time) / 500.).min(1.);
+        let bounds = self.bounds(skin);
+        draw_texture_ex(
+            &skin.play_skip,
+            bounds.x,
+            bounds.y,
+            Color::new(1., 1., 1., alpha),
+            DrawTextureParams {
+                dest_size: Some(bounds.size()),
+                ..Default::default()
+            },
+        );
+    }
+}
@@ -2,59 +2,191 @@ use crate::content::beatmap::TimingPoint;
 
 use super::music::MusicManager;
 
+/// The timing points in effect at some point in the song.
+#[derive(Clone, Debug)]
+pub struct TimingState {
+    pub uninherited: TimingPoint,
+    /// Only set when an inherited point comes after `uninherited`.
+    pub inherited: Option<TimingPoint>,
+    /// Kiai is toggled by the latest point of either kind.
+    pub kiai: bool,
+}
+
+impl TimingState {
+    pub fn velocity_multiplier(&self) -> f32 {
+        if let Some(inherited) = &self.inherited {
+            (-100.0 / inherited.ms_per_beat).clamp(0.1, 10.0)
+        } else {
+            1.0
+        }
+    }
+
+    pub fn beat_length(&self) -> f32 {
+        self.uninherited.ms_per_beat
+    }
+
+    pub fn bpm(&self) -> f32 {
+        60000.0 / self.uninherited.ms_per_beat
+    }
+
+    pub fn meter(&self) -> i32 {
+        self.uninherited.meter.max(1)
+    }
+}
+
 pub struct TimingPointManager {
-    pub current_uninherited: TimingPoint,
-    pub current_inherited: Option<TimingPoint>,
     points: Vec<TimingPoint>,
-    index: usize,
+    /// For each point, the index of the last uninherited point up to and including it.
+    uninherited: Vec<Option<usize>>,
+    /// Tempo used before the first uninherited point.
+    first_uninherited: usize,
+    pub current: TimingState,
 }
 
 impl TimingPointManager {
-    pub fn new(points: Vec<TimingPoint>) -> Self {
-        let first_uninherited = points
+    pub fn new(mut points: Vec<TimingPoint>) -> Self {
+        points.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+
+        let mut last = None;
+        let uninherited = points
             .iter()
-            .find(|p| p.ms_per_beat > 0.0)
-            .unwrap_or(&points[0])
-            .clone();
+            .enumerate()
+            .map(|(i, p)| {
+                if p.ms_per_beat > 0.0 {
+                    last = Some(i);
+                }
+                last
+            })
+            .collect::<Vec<_>>();
+        let first_uninherited = uninherited.iter().flatten().next().copied().unwrap_or(0);
 
-        Self {
-            current_uninherited: first_uninherited,
-            current_inherited: None,
+        let mut manager = Self {
+            current: TimingState {
+                uninherited: points[0].clone(),
+                inherited: None,
+                kiai: false,
+            },
             points,
-            index: 0,
+            uninherited,
+            first_uninherited,
+        };
+        manager.current = manager.at(f32::NEG_INFINITY);
+        manager
+    }
+
+    /// Timing at any time, so seeking works in both directions.
+    pub fn at(&self, time: f32) -> TimingState {
+        let last = self
+            .points
+            .partition_point(|p| p.offset <= time)
+            .checked_sub(1);
+
+        // Before the first uninherited point its tempo applies.
+        let uninherited = last
+            .and_then(|i| self.uninherited[i])
+            .unwrap_or(self.first_uninherited);
+        // The latest point is either the uninherited one or overrides its velocity
+        let latest = last.map(|i| &self.points[i]);
+
+        TimingState {
+            uninherited: self.points[uninherited].clone(),
+            inherited: latest.filter(|p| p.ms_per_beat < 0.0).cloned(),
+            kiai: latest.is_some_and(|p| p.kiai_mode),
         }
     }
 
     pub fn update(&mut self, music: &MusicManager) {
-        if self.index >= self.points.len() {
-            return;
-        }
+        self.seek(music.time);
+    }
 
-        let curr = &self.points[self.index];
-        if music.time.as_millis() as f32 >= curr.offset {
-            if curr.ms_per_beat > 0.0 {
-                self.current_uninherited = curr.clone();
-                self.current_inherited = None;
-            } else {
-                self.current_inherited = Some(curr.clone());
-            }
-            self.index += 1;
-        }
+    pub fn seek(&mut self, time: f32) {
+        self.current = self.at(time);
     }
 
-    pub fn velocity_multiplier(&self) -> f32 {
-        if let Some(inherited) = &self.current_inherited {
-            -inherited.ms_per_beat / 100.0
-        } else {
-            1.0
-        }
+    /// Beats since the uninherited point in effect at `time`. The fractional part is how far
+    /// into the current beat `time` is.
+    pub fn beat_at(&self, time: f32) -> f32 {
+        let state = self.at(time);
+        (time - state.uninherited.offset) / state.beat_length()
     }
 
-    pub fn beat_length(&self) -> f32 {
-        self.current_uninherited.ms_per_beat
+    /// Like `beat_at`, but counting measures.
+    pub fn measure_at(&self, time: f32) -> f32 {
+        let state = self.at(time);
+        (time - state.uninherited.offset) / (state.beat_length() * state.meter() as f32)
     }
 
     pub fn bpm(&self) -> f32 {
-        60000.0 / self.current_uninherited.ms_per_beat
+        self.current.bpm()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::beatmap::SampleSet;
+
+    fn point(offset: f32, ms_per_beat: f32, meter: i32) -> TimingPoint {
+        TimingPoint {
+            offset,
+            ms_per_beat,
+            meter,
+            sample_set: SampleSet::Auto,
+            sample_index: 0,
+            volume: 100,
+            inherited: ms_per_beat > 0.,
+            kiai_mode: false,
+        }
+    }
+
+    fn manager() -> TimingPointManager {
+        TimingPointManager::new(vec![
+            point(1000., 500., 4),
+            point(2000., -50., 4),
+            point(3000., 250., 3),
+            point(4000., -200., 3),
+        ])
+    }
+
+    #[test]
+    fn the_first_tempo_applies_before_the_first_point() {
+        let timing = manager();
+        let state = timing.at(0.);
+
+        assert_eq!(state.uninherited.offset, 1000.);
+        assert!(state.inherited.is_none());
+        assert_eq!(timing.beat_at(0.), -2.);
+        assert_eq!(timing.measure_at(0.), -0.5);
+    }
+
+    #[test]
+    fn a_point_applies_from_its_own_offset() {
+        let timing = manager();
+
+        assert_eq!(timing.at(1000.).uninherited.offset, 1000.);
+        assert_eq!(timing.at(1999.).velocity_multiplier(), 1.);
+        assert_eq!(timing.at(2000.).velocity_multiplier(), 2.);
+        assert_eq!(timing.at(3000.).uninherited.offset, 3000.);
+        assert_eq!(timing.measure_at(3000.), 0.);
+    }
+
+    #[test]
+    fn inherited_points_keep_the_latest_tempo() {
+        let timing = manager();
+
+        let state = timing.at(2500.);
+        assert_eq!(state.beat_length(), 500.);
+        assert_eq!(timing.beat_at(2500.), 3.);
+
+        // A new red line drops the velocity of the green line before it
+        let state = timing.at(3500.);
+        assert_eq!(state.beat_length(), 250.);
+        assert_eq!(state.velocity_multiplier(), 1.);
+
+        let state = timing.at(4500.);
+        assert_eq!(state.uninherited.offset, 3000.);
+        assert_eq!(state.meter(), 3);
+        assert_eq!(state.velocity_multiplier(), 0.5);
+        assert_eq!(timing.measure_at(4500.), 2.);
     }
 }
@@ -1,8 +1,15 @@
-use std::{io::Write, path::Path};
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
 
-use content::{beatmap::formats::osu::OsuParser, skin::Skin};
+use content::{beatmap::formats::Registry, skin::Skin};
 
-use game::Game;
+use game::{
+    Game,
+    input::InputConfig,
+    practice::{Practice, PracticeKeys},
+};
 use macroquad::prelude::*;
 
 mod content;
@@ -21,32 +28,81 @@ fn window_conf() -> Conf {
 
 #[macroquad::main(window_conf)]
 async fn main() {
+    let strict = std::env::args().any(|a| a == "--strict");
+    let practice = std::env::args().any(|a| a == "--practice");
+    // Converts the map to the format of the given path instead of playing it
+    let save_path = std::env::args().find_map(|a| a.strip_prefix("--save=").map(String::from));
+    // Skips the prompt, which can't be answered when the map came from stdin
+    let difficulty_arg = std::env::args().find_map(|a| {
+        a.strip_prefix("--difficulty=").map(|n| {
+            n.parse::<usize>()
+                .expect("Expected number for --difficulty")
+        })
+    });
     let map_path = std::env::args()
         .skip(1)
-        .next()
+        .find(|a| !a.starts_with("--"))
         .expect("Expected map path as argument");
-    let map = OsuParser::from_osz(map_path);
-    println!(
-        "Select a difficulty for: {}",
-        map.difficulties[0].metadata.title
-    );
-    for (i, diff) in map.difficulties.iter().enumerate() {
-        println!("{}. {}", i + 1, diff.metadata.version);
+    let registry = Registry::default();
+    // `-` reads an archive from stdin
+    let loaded = if map_path == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut bytes)
+            .expect("Failed to read stdin");
+        registry.load_bytes(bytes, !strict)
+    } else {
+        registry.load(Path::new(&map_path), !strict)
+    };
+    let map = match loaded {
+        Ok(map) => map,
+        Err(e) => {
+            println!("Failed to load {}: {}", map_path, e);
+            return;
+        }
+    };
+    for warning in &map.warnings {
+        println!("warning: {}", warning);
     }
 
-    print!("> ");
-    std::io::stdout().flush().unwrap();
-    let mut inp = String::new();
-    std::io::stdin()
-        .read_line(&mut inp)
-        .expect("Expected input");
-    let selected: usize = inp.trim().parse().expect("Expected number input");
+    if let Some(save_path) = save_path {
+        match registry.save(&map, Path::new(&save_path)) {
+            Ok(()) => println!("Saved {}", save_path),
+            Err(e) => println!("Failed to save {}: {}", save_path, e),
+        }
+        return;
+    }
+
+    let selected = difficulty_arg.unwrap_or_else(|| {
+        println!(
+            "Select a difficulty for: {}",
+            map.difficulties[0].metadata.title
+        );
+        for (i, diff) in map.difficulties.iter().enumerate() {
+            println!("{}. {}", i + 1, diff.metadata.version);
+        }
+
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+        let mut inp = String::new();
+        std::io::stdin()
+            .read_line(&mut inp)
+            .expect("Expected input");
+        inp.trim().parse().expect("Expected number input")
+    });
 
     if selected < 1 || selected > map.difficulties.len() {
         println!("Input not in correct range");
     }
 
     let skin = Skin::load(Path::new("skin/")).await;
-    let mut game = Game::new(&skin, &map.difficulties[selected - 1]);
+    let difficulty = &map.difficulties[selected - 1];
+    let mut game = Game::new(&skin, difficulty, InputConfig::default());
+    if practice {
+        game.enable_practice(Practice::new(
+            &difficulty.editor.bookmarks,
+            PracticeKeys::default(),
+        ));
+    }
     game.play().await;
 }